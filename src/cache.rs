@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::config::Config;
+use crate::{dotfile, git};
+use crate::resolve::{self, ProfileSource, Resolution};
+
+/// Per-directory cache of resolved profiles, so repeated `gsw auto` runs in the same
+/// repo skip the dotfile walk and git config reads while nothing relevant changes
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ResolutionCache {
+    entries: HashMap<String, Entry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    profile: String,
+    /// The .gswitch file the profile came from; `None` means the repo's git config
+    file: Option<PathBuf>,
+    inputs: Vec<Input>,
+}
+
+/// A file resolution depends on, as last seen: absent, or its mtime and size
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Input {
+    path: PathBuf,
+    modified: Option<u64>,
+    len: u64,
+}
+
+impl Input {
+    fn read(path: PathBuf) -> Self {
+        let metadata = std::fs::metadata(&path).ok();
+        let modified = metadata
+            .as_ref()
+            .and_then(|metadata| metadata.modified().ok())
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_nanos() as u64);
+        let len = metadata.map_or(0, |metadata| metadata.len());
+        Input { path, modified, len }
+    }
+}
+
+/// `$XDG_STATE_HOME/gswitch/resolve-cache.json`, falling back to `~/.local/state`
+pub fn cache_path() -> Option<PathBuf> {
+    let state_home = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local/state")))?;
+    Some(state_home.join("gswitch").join("resolve-cache.json"))
+}
+
+/// Like `resolve::resolve_profile_in_dir` for `dir` (default the current directory), but
+/// reusing the last result while the dotfiles between there and the repo root, the
+/// repo's git config and config.toml are unchanged. An explicit `file` bypasses the cache.
+pub fn resolve_profile_cached(file: Option<&Path>, dir: Option<&Path>) -> Result<Resolution> {
+    let start = dir.map(Path::to_path_buf).map_or_else(std::env::current_dir, Ok);
+    let (Some(cache_path), None, Ok(start)) = (cache_path(), file, start) else {
+        return resolve::resolve_profile_in_dir(file, dir);
+    };
+    resolve_with_cache(&cache_path, &start)
+}
+
+fn resolve_with_cache(cache_path: &Path, dir: &Path) -> Result<Resolution> {
+    let dir = dir.canonicalize()?;
+    let Some(root) = git::find_repo_root_in_dir(Some(&dir)) else {
+        return Ok(Resolution::NotARepo);
+    };
+    // Worktrees keep their config elsewhere, so there's no single file to watch
+    if !root.join(".git").is_dir() {
+        return resolve::resolve_profile_in_dir(None, Some(&dir));
+    }
+    let inputs = inputs_for(&dir, &root, dotfile::subpath());
+    let mut cache = load(cache_path);
+    let key = dir.to_string_lossy().into_owned();
+
+    if let Some(entry) = cache.entries.get(&key)
+        && entry.inputs == inputs
+    {
+        let source = entry.file.clone().map_or(ProfileSource::GitConfig, ProfileSource::File);
+        return Ok(Resolution::Found(entry.profile.clone(), source));
+    }
+
+    let resolution = resolve::resolve_profile_in_dir(None, Some(&dir))?;
+    if let Resolution::Found(profile, source) = &resolution {
+        let file = match source {
+            ProfileSource::File(path) => Some(path.clone()),
+            ProfileSource::GitConfig => None,
+            // Url rules are applied after the cache, so they never end up in it
+            ProfileSource::UrlRule(_) => return Ok(resolution),
+        };
+        cache.entries.retain(|dir, _| Path::new(dir).is_dir());
+        cache.entries.insert(key, Entry { profile: profile.clone(), file, inputs });
+        // The cache is only an optimization, so failing to write it isn't an error
+        if let Some(parent) = cache_path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        if let Ok(content) = serde_json::to_string(&cache) {
+            let _ = std::fs::write(cache_path, content);
+        }
+    }
+    Ok(resolution)
+}
+
+fn load(cache_path: &Path) -> ResolutionCache {
+    std::fs::read_to_string(cache_path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Every file whose change could alter the resolution for `dir`
+fn inputs_for(dir: &Path, root: &Path, subpath: Option<&Path>) -> Vec<Input> {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let mut paths: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|ancestor| ancestor.starts_with(&root))
+        .flat_map(|ancestor| dotfile::dotfile_candidates(ancestor, subpath))
+        .collect();
+    // gswitch.profile lives in the repo's own config
+    paths.push(root.join(".git").join("config"));
+    if let Ok(config_path) = Config::config_path() {
+        paths.push(config_path);
+    }
+    paths.into_iter().map(Input::read).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_cache_hit_and_invalidation() {
+        with_git_repo(|repo| {
+            let cache_path = repo.join("cache/resolve-cache.json");
+            let subdir = repo.create_dir("src").unwrap();
+            repo.create_file(".gswitch", "work\n").unwrap();
+
+            let resolution = resolve_with_cache(&cache_path, &subdir).unwrap();
+            assert!(matches!(resolution, Resolution::Found(ref name, _) if name == "work"));
+            assert!(cache_path.exists());
+
+            // Poison the cached entry: a hit returns it without re-resolving
+            let content = std::fs::read_to_string(&cache_path).unwrap();
+            std::fs::write(&cache_path, content.replace("\"work\"", "\"cached\"")).unwrap();
+            let resolution = resolve_with_cache(&cache_path, &subdir).unwrap();
+            assert!(matches!(resolution, Resolution::Found(ref name, _) if name == "cached"));
+
+            // Editing the .gswitch file invalidates the entry
+            repo.create_file(".gswitch", "personal\n").unwrap();
+            let resolution = resolve_with_cache(&cache_path, &subdir).unwrap();
+            assert!(matches!(resolution, Resolution::Found(ref name, _) if name == "personal"));
+        });
+    }
+
+    #[test]
+    fn test_cache_invalidated_by_nearer_dotfile() {
+        with_git_repo(|repo| {
+            let cache_path = repo.join("cache/resolve-cache.json");
+            let subdir = repo.create_dir("src").unwrap();
+            repo.create_file(".gswitch", "work\n").unwrap();
+            resolve_with_cache(&cache_path, &subdir).unwrap();
+
+            repo.create_file("src/.gswitch", "oss\n").unwrap();
+            let resolution = resolve_with_cache(&cache_path, &subdir).unwrap();
+            assert!(matches!(resolution, Resolution::Found(ref name, _) if name == "oss"));
+        });
+    }
+
+    #[test]
+    fn test_cache_watches_dotfile_subpath() {
+        with_git_repo(|repo| {
+            let subdir = repo.create_dir("src").unwrap();
+            let root = repo.join(".").canonicalize().unwrap();
+            let subdir = subdir.canonicalize().unwrap();
+            let inputs = inputs_for(&subdir, &root, Some(Path::new(".config/gswitch/profile")));
+
+            repo.create_dir("src/.config/gswitch").unwrap();
+            repo.create_file("src/.config/gswitch/profile", "oss\n").unwrap();
+            let changed = inputs_for(&subdir, &root, Some(Path::new(".config/gswitch/profile")));
+            assert_ne!(inputs, changed);
+        });
+    }
+}
@@ -1,40 +1,191 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
-use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result, bail};
+use crate::{keys, shell, warnings};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 pub struct GitProfile {
     pub name: String,
     pub email: String,
     pub signing_key: Option<String>,
+    /// Maps to `gpg.format` (openpgp, x509 or ssh)
+    pub signing_format: Option<String>,
+    /// Maps to `gpg.program`, or `gpg.ssh.program` when the signing format is ssh
+    pub signing_program: Option<String>,
+    /// Maps to `gpg.ssh.allowedSignersFile`, used to verify ssh signatures
+    pub allowed_signers_file: Option<String>,
+    /// Maps to `user.useConfigOnly`, stopping git from guessing an identity
+    pub require_explicit: Option<bool>,
+    /// Maps to `pull.rebase` (true, false, merges or interactive)
+    pub pull_rebase: Option<String>,
+    /// Maps to `push.autoSetupRemote`
+    pub push_auto_setup_remote: Option<bool>,
+    /// Maps to `init.defaultBranch`
+    pub init_default_branch: Option<String>,
+    /// Maps to `fetch.prune`
+    pub fetch_prune: Option<bool>,
+    /// Maps to `core.sshCommand`, e.g. to pick the key for a second GitHub account
+    pub ssh_command: Option<String>,
+    /// Maps to `http.proxy`, e.g. a corporate proxy only the work identity goes through
+    pub http_proxy: Option<String>,
+    /// Shell command run after `switch`, `use` or `local` applies this profile
+    pub on_switch: Option<String>,
+    /// Scope `gsw use` applies this profile at when `--scope` isn't given (local or global)
+    pub scope_default: Option<String>,
+    /// Display position in `gsw list`; profiles without one follow, by name
+    pub order: Option<u32>,
+    /// When `switch`, `use` or `local` last applied this profile, in Unix milliseconds
+    pub last_used: Option<u64>,
+    /// How many times `switch`, `use` or `local` applied this profile
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub use_count: u64,
+    /// Remote hosts to sign commits for: when set, `auto` and `local` turn
+    /// `commit.gpgsign` on only in repos whose `origin` is on one of them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sign_hosts: Vec<String>,
+    /// Other names `switch`, `use`, `local` and `init` accept for this profile
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Extra environment variables exported by `gsw auto --export-env`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Distributed by the organization policy bundle; can be switched to but not edited
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub managed: bool,
+}
+
+/// Picks a profile from a repo's `origin` URL when nothing else names one
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UrlRule {
+    /// Regex matched against the URL. When it has a capture group (e.g.
+    /// `github.com/(?P<org>[^/]+)/`), a profile named after the capture wins.
+    pub pattern: String,
+    /// Profile used when the pattern matches but no profile is named after the capture
+    pub profile: Option<String>,
+}
+
+/// A single configuration problem found by `Config::validate`
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Problem {
+    pub profile: String,
+    pub field: String,
+    pub problem: String,
+}
+
+impl Problem {
+    fn new(profile: &str, field: &str, problem: impl Into<String>) -> Self {
+        Problem {
+            profile: profile.to_string(),
+            field: field.to_string(),
+            problem: problem.into(),
+        }
+    }
+}
+
+impl GitProfile {
+    /// `export` lines for this identity's git author/committer variables and custom env
+    pub fn export_lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            shell::export_line("GIT_AUTHOR_NAME", &self.name),
+            shell::export_line("GIT_AUTHOR_EMAIL", &self.email),
+            shell::export_line("GIT_COMMITTER_NAME", &self.name),
+            shell::export_line("GIT_COMMITTER_EMAIL", &self.email),
+        ];
+        lines.extend(self.env.iter().map(|(key, value)| shell::export_line(key, value)));
+        lines
+    }
+
+    /// Fail for a profile still missing its name or email, like the skeletons
+    /// `import-ssh-config` creates, so applying one never writes an empty identity
+    pub fn check_complete(&self) -> Result<()> {
+        if self.name.trim().is_empty() || self.email.trim().is_empty() {
+            bail!("Profile has no name or email yet: complete it with `gsw add <profile> --user-name <name> --email <email>`");
+        }
+        Ok(())
+    }
+}
+
+/// Write profiles in name order, so saving never reshuffles config.toml
+fn serialize_sorted<S: serde::Serializer>(
+    profiles: &HashMap<String, GitProfile>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    profiles.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[derive(Default)]
 pub struct Config {
+    #[serde(serialize_with = "serialize_sorted")]
     pub profiles: HashMap<String, GitProfile>,
     pub current_profile: Option<String>,
+    /// The globally current profile before the last switch, for `gsw switch -`
+    pub previous_profile: Option<String>,
+    /// Where `gsw policy sync` fetches the organization's profile bundle from
+    pub policy_url: Option<String>,
+    /// Always behave as if `gsw auto --no-boundary` was passed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_boundary: bool,
+    /// In `gsw auto`, fall back to `~/.gswitch` when nothing else names a profile
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub home_fallback: bool,
+    /// Write global switches to `global_include_path` (included from `~/.gitconfig`
+    /// by `gsw setup-global-include`) instead of `~/.gitconfig` itself
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub global_include: bool,
+    /// Always behave as if `--strict` was passed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strict: bool,
+    /// Path checked for a dotfile in each directory besides `.gswitch`,
+    /// relative to that directory (e.g. `.config/gswitch/profile`)
+    pub dotfile_subpath: Option<String>,
+    /// File that `switch`, `use`, `local` and `auto` write the newly active profile name to
+    pub notify_file: Option<String>,
+    /// Rules mapping remote URLs to profiles, tried in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub url_rules: Vec<UrlRule>,
+    /// Set by `--no-save`: keep changes in memory and make `save` a no-op
+    #[serde(skip)]
+    pub no_save: bool,
+    /// Set by `--no-hooks`: don't run `on_switch` commands
+    #[serde(skip)]
+    pub no_hooks: bool,
 }
 
 
 impl Config {
+    /// `config.toml` in the gswitch config dir, or `config.<env>.toml` when
+    /// `GSWITCH_ENV` selects another profile set
     pub fn config_path() -> Result<PathBuf> {
-        // Check for test override first
-        if let Ok(test_config_home) = std::env::var("XDG_CONFIG_HOME") {
-            let config_dir = std::path::PathBuf::from(test_config_home).join("gswitch");
-            return Ok(config_dir.join("config.toml"));
-        }
-        
+        let file_name = match std::env::var("GSWITCH_ENV") {
+            Ok(env) if !env.is_empty() => {
+                if env.contains(['/', '\\', '.']) {
+                    bail!("GSWITCH_ENV '{}' must not contain '/', '\\' or '.'", env);
+                }
+                format!("config.{}.toml", env)
+            }
+            _ => "config.toml".to_string(),
+        };
+
         // Use XDG config directory standard for Unix-like systems
         let config_dir = if let Ok(xdg_config_home) = std::env::var("XDG_CONFIG_HOME") {
             std::path::PathBuf::from(xdg_config_home)
-        } else {
-            let home = std::env::var("HOME").context("HOME environment variable not set")?;
+        } else if let Ok(home) = std::env::var("HOME") {
             std::path::PathBuf::from(home).join(".config")
+        } else {
+            // Without either variable (some CI and container setups), ask the platform,
+            // which can still find the home directory from the user database
+            dirs::config_dir()
+                .context("Could not determine the config directory: set XDG_CONFIG_HOME or HOME")?
         };
         
-        Ok(config_dir.join("gswitch").join("config.toml"))
+        Ok(config_dir.join("gswitch").join(file_name))
+    }
+
+    /// The git config file gswitch owns when `global_include` is on, next to config.toml
+    pub fn global_include_path() -> Result<PathBuf> {
+        Ok(Self::config_path()?.with_file_name("gitconfig"))
     }
 
     pub fn load() -> Result<Self> {
@@ -44,14 +195,96 @@ impl Config {
             return Ok(Self::default());
         }
 
-        let content = std::fs::read_to_string(&config_path)
+        Self::load_from(&config_path)
+    }
+
+    /// Read and check the config file at `config_path`, which must exist
+    pub fn load_from(config_path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(config_path)
             .context("Failed to read config file")?;
         
-        toml::from_str(&content)
-            .context("Failed to parse config file")
+        let config: Self = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse config file {}", config_path.display()))?;
+        config.check()?;
+        Ok(config)
+    }
+
+    /// Like `load`, but a malformed profile table doesn't fail the whole file:
+    /// each profile is parsed on its own, and the ones that fail are skipped
+    /// and reported as `"<name>: <error>"`
+    pub fn load_lenient() -> Result<(Self, Vec<String>)> {
+        let config_path = Self::config_path()?;
+        if !config_path.exists() {
+            return Ok((Self::default(), Vec::new()));
+        }
+        let content = std::fs::read_to_string(&config_path)
+            .context("Failed to read config file")?;
+        Self::parse_lenient(&content)
+    }
+
+    fn parse_lenient(content: &str) -> Result<(Self, Vec<String>)> {
+        let mut table: toml::Table = toml::from_str(content)
+            .context("Failed to parse config file")?;
+        let profiles = match table.remove("profiles") {
+            Some(toml::Value::Table(profiles)) => profiles,
+            Some(_) => bail!("Failed to parse config file: `profiles` must be a table"),
+            None => toml::Table::new(),
+        };
+        table.insert("profiles".to_string(), toml::Value::Table(toml::Table::new()));
+
+        let mut config: Self = toml::Value::Table(table)
+            .try_into()
+            .context("Failed to parse config file")?;
+        config.check()?;
+
+        let mut errors = Vec::new();
+        for (name, value) in profiles {
+            match value.try_into::<GitProfile>() {
+                Ok(profile) => {
+                    config.profiles.insert(name, profile);
+                }
+                Err(err) => errors.push(format!("{}: {}", name, err.message())),
+            }
+        }
+        errors.sort();
+        Ok((config, errors))
+    }
+
+    /// Reject settings serde accepts but gswitch can't use
+    fn check(&self) -> Result<()> {
+        if let Some(subpath) = &self.dotfile_subpath {
+            let path = Path::new(subpath);
+            let relative = path.components().all(|part| matches!(part, std::path::Component::Normal(_)));
+            if subpath.is_empty() || !relative {
+                bail!("dotfile_subpath '{}' must be a relative path without '..'", subpath);
+            }
+        }
+        for rule in &self.url_rules {
+            regex::Regex::new(&rule.pattern)
+                .with_context(|| format!("Invalid url_rules pattern '{}'", rule.pattern))?;
+        }
+        Ok(())
+    }
+
+    /// The profile the first matching url rule selects for `url`, and that rule's pattern
+    pub fn match_url_rules(&self, url: &str) -> Option<(String, &str)> {
+        self.url_rules.iter().find_map(|rule| {
+            let captures = regex::Regex::new(&rule.pattern).ok()?.captures(url)?;
+            let captured = captures
+                .iter()
+                .skip(1)
+                .flatten()
+                .map(|capture| capture.as_str())
+                .find(|name| self.profiles.contains_key(*name));
+            let profile = captured.map(str::to_string).or_else(|| rule.profile.clone())?;
+            Some((profile, rule.pattern.as_str()))
+        })
     }
 
     pub fn save(&self) -> Result<()> {
+        if self.no_save {
+            return Ok(());
+        }
         let config_path = Self::config_path()?;
         
         if let Some(parent) = config_path.parent() {
@@ -61,11 +294,78 @@ impl Config {
 
         let content = toml::to_string_pretty(self)
             .context("Failed to serialize config")?;
-        
-        std::fs::write(&config_path, content)
+
+        // Write a sibling file and rename it over the config, so a crash or full disk
+        // never leaves a truncated config behind. A symlinked config (e.g. from a
+        // dotfiles repo) is written through, and the old file's permissions are kept.
+        let config_path = config_path.canonicalize().unwrap_or(config_path);
+        let mut temp_path = config_path.clone().into_os_string();
+        temp_path.push(".tmp");
+        std::fs::write(&temp_path, content)
+            .context("Failed to write config file")?;
+        if let Ok(metadata) = std::fs::metadata(&config_path) {
+            std::fs::set_permissions(&temp_path, metadata.permissions())
+                .context("Failed to write config file")?;
+        }
+        std::fs::rename(&temp_path, &config_path)
             .context("Failed to write config file")
     }
 
+    /// Sort each profile's order-insensitive lists (`aliases`, `sign_hosts`).
+    /// `url_rules` keep their order, since the first match wins.
+    pub fn sort_lists(&mut self) {
+        for profile in self.profiles.values_mut() {
+            profile.aliases.sort();
+            profile.sign_hosts.sort();
+        }
+    }
+
+    /// Fix stray values left by hand edits: references to missing profiles, whitespace
+    /// around identities and empty optional settings. Returns a line per change.
+    pub fn clean(&mut self) -> Vec<String> {
+        let mut changes = Vec::new();
+        for (field, reference) in [
+            ("current_profile", &mut self.current_profile),
+            ("previous_profile", &mut self.previous_profile),
+        ] {
+            if let Some(name) = reference
+                && !self.profiles.contains_key(name.as_str())
+            {
+                changes.push(format!("Removed {} '{}', which names no profile", field, name));
+                *reference = None;
+            }
+        }
+
+        let mut names: Vec<String> = self.profiles.keys().cloned().collect();
+        names.sort();
+        for name in names {
+            let profile = self.profiles.get_mut(&name).expect("name comes from the map");
+            for (field, value) in [("name", &mut profile.name), ("email", &mut profile.email)] {
+                if value.trim() != value.as_str() {
+                    changes.push(format!("Trimmed whitespace around {} of '{}'", field, name));
+                    *value = value.trim().to_string();
+                }
+            }
+            for (field, value) in [
+                ("signing_key", &mut profile.signing_key),
+                ("signing_format", &mut profile.signing_format),
+                ("signing_program", &mut profile.signing_program),
+                ("pull_rebase", &mut profile.pull_rebase),
+                ("init_default_branch", &mut profile.init_default_branch),
+                ("ssh_command", &mut profile.ssh_command),
+                ("http_proxy", &mut profile.http_proxy),
+                ("on_switch", &mut profile.on_switch),
+                ("scope_default", &mut profile.scope_default),
+            ] {
+                if value.as_deref().is_some_and(|value| value.trim().is_empty()) {
+                    changes.push(format!("Removed empty {} from '{}'", field, name));
+                    *value = None;
+                }
+            }
+        }
+        changes
+    }
+
     pub fn add_profile(&mut self, name: String, profile: GitProfile) {
         self.profiles.insert(name, profile);
     }
@@ -74,16 +374,350 @@ impl Config {
         if self.current_profile.as_ref() == Some(&name.to_string()) {
             self.current_profile = None;
         }
+        if self.previous_profile.as_deref() == Some(name) {
+            self.previous_profile = None;
+        }
         self.profiles.remove(name).is_some()
     }
 
+    /// Rename a profile, updating every reference to it in the config
+    /// (`current_profile`, `previous_profile` and `url_rules`) so none dangle
+    pub fn rename_profile(&mut self, old: &str, new: &str) -> Result<()> {
+        if self.is_managed(old) {
+            bail!("Profile '{}' is managed by policy and can't be renamed", old);
+        }
+        if self.profiles.contains_key(new) {
+            bail!("Profile '{}' already exists", new);
+        }
+        if let Some(collision) = self.alias_collision(new, &[]) {
+            bail!("Can't rename to {}", collision);
+        }
+        let Some(profile) = self.profiles.remove(old) else {
+            bail!("Profile '{}' not found", old);
+        };
+        self.profiles.insert(new.to_string(), profile);
+
+        for reference in [&mut self.current_profile, &mut self.previous_profile] {
+            if reference.as_deref() == Some(old) {
+                *reference = Some(new.to_string());
+            }
+        }
+        for rule in &mut self.url_rules {
+            if rule.profile.as_deref() == Some(old) {
+                rule.profile = Some(new.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `name` is a policy-managed profile that users can't edit
+    pub fn is_managed(&self, name: &str) -> bool {
+        self.profiles.get(name).is_some_and(|profile| profile.managed)
+    }
+
+    /// Replace all managed profiles with `bundle`. A user's own profile of the same
+    /// name is kept (with a warning) rather than overwritten, and `current_profile` or
+    /// `previous_profile` naming a dropped managed profile is cleared.
+    /// Returns the number of managed profiles now configured.
+    pub fn replace_managed_profiles(&mut self, bundle: HashMap<String, GitProfile>) -> usize {
+        self.profiles.retain(|_, profile| !profile.managed);
+        let mut bundle: Vec<(String, GitProfile)> = bundle.into_iter().collect();
+        bundle.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut count = 0;
+        for (name, mut profile) in bundle {
+            if self.profiles.contains_key(&name) {
+                warnings::warn(format!("Skipping managed profile '{}': a profile of your own has that name", name));
+                continue;
+            }
+            profile.managed = true;
+            self.profiles.insert(name, profile);
+            count += 1;
+        }
+        if self.current_profile.as_deref().is_some_and(|name| self.get_profile(name).is_none()) {
+            self.current_profile = None;
+        }
+        if self.previous_profile.as_deref().is_some_and(|name| self.get_profile(name).is_none()) {
+            self.previous_profile = None;
+        }
+        count
+    }
+
     pub fn get_profile(&self, name: &str) -> Option<&GitProfile> {
-        self.profiles.get(name)
+        self.profiles.get(self.canonical_name(name))
+    }
+
+    /// The profile key `name` refers to: itself, or the profile that lists it as an alias
+    pub fn canonical_name<'a>(&'a self, name: &'a str) -> &'a str {
+        if self.profiles.contains_key(name) {
+            return name;
+        }
+        self.profiles
+            .iter()
+            .find(|(_, profile)| profile.aliases.iter().any(|alias| alias == name))
+            .map_or(name, |(key, _)| key.as_str())
+    }
+
+    /// Why profile `name` can't be saved with `aliases`, if an alias (or the name
+    /// itself) is already taken by another profile's name or aliases
+    pub fn alias_collision(&self, name: &str, aliases: &[String]) -> Option<String> {
+        let others = || self.profiles.iter().filter(|(key, _)| key.as_str() != name);
+        for alias in aliases {
+            if alias == name {
+                return Some(format!("alias '{}' is the profile's own name", alias));
+            }
+            if self.profiles.contains_key(alias) {
+                return Some(format!("alias '{}' is already a profile name", alias));
+            }
+            if let Some((key, _)) = others().find(|(_, profile)| profile.aliases.contains(alias)) {
+                return Some(format!("alias '{}' is already an alias of '{}'", alias, key));
+            }
+        }
+        others()
+            .find(|(_, profile)| profile.aliases.iter().any(|alias| alias == name))
+            .map(|(key, _)| format!("'{}' is already an alias of '{}'", name, key))
     }
 
     pub fn set_current_profile(&mut self, name: String) {
+        if self.current_profile.as_ref() != Some(&name) {
+            self.previous_profile = self.current_profile.take();
+        }
         self.current_profile = Some(name);
     }
+
+    /// Profiles in display order: explicit `order` first (ascending), then by name
+    pub fn sorted_profiles(&self) -> Vec<(&String, &GitProfile)> {
+        let mut profiles: Vec<(&String, &GitProfile)> = self.profiles.iter().collect();
+        profiles.sort_by_key(|(name, profile)| (profile.order.is_none(), profile.order, *name));
+        profiles
+    }
+
+    /// Whether nothing points at profile `name`: not `current_profile` or
+    /// `previous_profile`, not a url rule's fallback, and not among `referenced`
+    /// (names read elsewhere, e.g. from .gswitch files, which may be aliases).
+    /// Profiles only reached through a url rule's capture group count as orphans.
+    pub fn is_orphan(&self, name: &str, referenced: &[String]) -> bool {
+        let pointers = [&self.current_profile, &self.previous_profile];
+        !pointers.into_iter().any(|pointer| pointer.as_deref() == Some(name))
+            && !self.url_rules.iter().any(|rule| rule.profile.as_deref() == Some(name))
+            && !referenced.iter().any(|reference| self.canonical_name(reference) == name)
+    }
+
+    /// Profiles most recently applied first; never-used profiles follow, by name
+    pub fn recent_profiles(&self) -> Vec<(&String, &GitProfile)> {
+        let mut profiles: Vec<(&String, &GitProfile)> = self.profiles.iter().collect();
+        profiles.sort_by_key(|(name, profile)| (std::cmp::Reverse(profile.last_used), *name));
+        profiles
+    }
+
+    /// Record that a profile was just applied, for `gsw list --recent` and `--usage-json`
+    pub fn mark_used(&mut self, name: &str) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        if let Some(profile) = self.profiles.get_mut(name) {
+            profile.last_used = Some(now);
+            profile.use_count += 1;
+        }
+    }
+
+    /// Find the profile whose identity matches the given git identity.
+    /// When several match, the alphabetically first name wins.
+    pub fn find_profile_matching(&self, identity: &GitProfile) -> Option<String> {
+        self.profiles
+            .iter()
+            .filter(|(_, profile)| profile.name == identity.name && profile.email == identity.email)
+            .map(|(key, _)| key)
+            .min()
+            .cloned()
+    }
+
+    /// Group profile names sharing the same name, email and signing key.
+    /// Only groups with more than one member are returned, sorted for stable output.
+    pub fn duplicate_identity_groups(&self) -> Vec<Vec<String>> {
+        let mut groups: HashMap<(&str, &str, Option<&str>), Vec<String>> = HashMap::new();
+        // Managed profiles come back on the next policy sync, so never merge them away,
+        // and skeletons from `import-ssh-config` have no identity to compare yet
+        for (key, profile) in self
+            .profiles
+            .iter()
+            .filter(|(_, profile)| !profile.managed && !profile.email.is_empty())
+        {
+            groups
+                .entry((&profile.name, &profile.email, profile.signing_key.as_deref()))
+                .or_default()
+                .push(key.clone());
+        }
+
+        let mut duplicates: Vec<Vec<String>> = groups
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .map(|mut group| {
+                group.sort();
+                group
+            })
+            .collect();
+        duplicates.sort();
+        duplicates
+    }
+
+    /// Check every profile and reference for problems, sorted by profile name.
+    /// With `check_keys`, signing keys are also looked up in the keyring/filesystem.
+    pub fn validate(&self, check_keys: bool) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        for (key, profile) in self.sorted_profiles() {
+            if profile.name.trim().is_empty() {
+                problems.push(Problem::new(key, "name", "is empty"));
+            } else if profile.name.trim() != profile.name {
+                problems.push(Problem::new(key, "name", "has leading or trailing whitespace"));
+            }
+            if let Err(e) = validate_email(&profile.email) {
+                problems.push(Problem::new(key, "email", e.to_string()));
+            }
+            if let Some(branch) = &profile.init_default_branch
+                && let Err(e) = validate_branch_name(branch)
+            {
+                problems.push(Problem::new(key, "init_default_branch", e.to_string()));
+            }
+            if let Some(scope) = &profile.scope_default
+                && scope != "local"
+                && scope != "global"
+            {
+                problems.push(Problem::new(key, "scope_default", "must be 'local' or 'global'"));
+            }
+            if check_keys
+                && let Some(signing_key) = &profile.signing_key
+                && !keys::signing_key_available(signing_key, profile.signing_format.as_deref())
+            {
+                problems.push(Problem::new(key, "signing_key", format!("key '{}' not found", signing_key)));
+            }
+        }
+
+        for group in self.duplicate_identity_groups() {
+            for duplicate in &group[1..] {
+                problems.push(Problem::new(duplicate, "identity", format!("duplicates profile '{}'", group[0])));
+            }
+        }
+
+        if let Some(current) = &self.current_profile
+            && !self.profiles.contains_key(current)
+        {
+            problems.push(Problem::new(current, "current_profile", "references a profile that doesn't exist"));
+        }
+
+        problems.sort_by(|a, b| a.profile.cmp(&b.profile));
+        problems
+    }
+
+    /// Remove `duplicate` and point anything that referenced it at `keeper`:
+    /// `current_profile`, `previous_profile`, `url_rules` and its aliases
+    pub fn merge_profile_into(&mut self, duplicate: &str, keeper: &str) {
+        if self.current_profile.as_deref() == Some(duplicate) {
+            self.current_profile = Some(keeper.to_string());
+        }
+        if self.previous_profile.as_deref() == Some(duplicate) {
+            self.previous_profile = Some(keeper.to_string());
+        }
+        for rule in &mut self.url_rules {
+            if rule.profile.as_deref() == Some(duplicate) {
+                rule.profile = Some(keeper.to_string());
+            }
+        }
+        if let Some(removed) = self.profiles.remove(duplicate)
+            && let Some(kept) = self.profiles.get_mut(keeper)
+        {
+            for alias in removed.aliases {
+                if !kept.aliases.contains(&alias) {
+                    kept.aliases.push(alias);
+                }
+            }
+        }
+    }
+}
+
+/// Pick the profile that survives a dedupe: the requested one if it's in the
+/// group, otherwise the lexicographically first name
+pub fn dedupe_keeper<'a>(group: &'a [String], keep: Option<&str>) -> &'a str {
+    keep.and_then(|k| group.iter().find(|name| name.as_str() == k))
+        .or_else(|| group.iter().min())
+        .map(String::as_str)
+        .unwrap_or_default()
+}
+
+/// Clean up a signing key as typed or pasted by the user.
+///
+/// Surrounding whitespace is always trimmed. SSH keys (by format, or anything
+/// that looks like a key path or literal key) are otherwise left untouched.
+/// OpenPGP key ids lose a leading `0x` unless `keep_prefix` is set, and must be
+/// hexadecimal unless they name a user id by email.
+pub fn normalize_signing_key(key: &str, format: Option<&str>, keep_prefix: bool) -> Result<String> {
+    let key = key.trim();
+    if key.is_empty() {
+        bail!("Signing key is empty");
+    }
+
+    if keys::is_ssh_key(key, format) || format == Some("x509") {
+        return Ok(key.to_string());
+    }
+
+    if key.contains('@') {
+        return Ok(key.to_string());
+    }
+
+    let (prefix, id) = match key.strip_prefix("0x").or_else(|| key.strip_prefix("0X")) {
+        Some(id) => (&key[..2], id),
+        None => ("", key),
+    };
+    // gpg accepts a trailing `!` to force a specific subkey
+    let hex = id.strip_suffix('!').unwrap_or(id);
+    if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("Signing key '{}' is not a valid GPG key id (expected hexadecimal)", key);
+    }
+
+    if keep_prefix {
+        Ok(format!("{}{}", prefix, id))
+    } else {
+        Ok(id.to_string())
+    }
+}
+
+fn is_zero(count: &u64) -> bool {
+    *count == 0
+}
+
+/// Basic sanity check for an email address: one `@`, a dotted domain, no spaces
+pub fn validate_email(email: &str) -> Result<()> {
+    let valid = match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !email.chars().any(char::is_whitespace)
+        }
+        None => false,
+    };
+    if !valid {
+        bail!("'{}' is not a valid email address", email);
+    }
+    Ok(())
+}
+
+/// Check a branch name is usable as `init.defaultBranch`
+pub fn validate_branch_name(branch: &str) -> Result<()> {
+    let invalid = branch.is_empty()
+        || branch.starts_with('-')
+        || branch.starts_with('/')
+        || branch.ends_with('/')
+        || branch.ends_with(".lock")
+        || branch.contains("..")
+        || branch.contains("@{")
+        || branch.chars().any(|c| c.is_whitespace() || c.is_control() || "~^:?*[\\".contains(c));
+    if invalid {
+        bail!("'{}' is not a valid branch name", branch);
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -105,6 +739,7 @@ mod tests {
             name: "Test User".to_string(),
             email: "test@example.com".to_string(),
             signing_key: None,
+            ..Default::default()
         };
         
         config.add_profile("test".to_string(), profile.clone());
@@ -120,6 +755,7 @@ mod tests {
             name: "Test User".to_string(),
             email: "test@example.com".to_string(),
             signing_key: Some("ABC123".to_string()),
+            ..Default::default()
         };
         
         config.add_profile("test".to_string(), profile.clone());
@@ -135,6 +771,7 @@ mod tests {
             name: "Test User".to_string(),
             email: "test@example.com".to_string(),
             signing_key: None,
+            ..Default::default()
         };
         
         config.add_profile("test".to_string(), profile);
@@ -155,6 +792,7 @@ mod tests {
             name: "Test User".to_string(),
             email: "test@example.com".to_string(),
             signing_key: None,
+            ..Default::default()
         };
         
         config.add_profile("test".to_string(), profile);
@@ -171,6 +809,149 @@ mod tests {
         assert_eq!(config.current_profile, Some("test".to_string()));
     }
 
+    #[test]
+    fn test_set_current_profile_tracks_previous() {
+        let mut config = Config::default();
+        config.set_current_profile("work".to_string());
+        assert!(config.previous_profile.is_none());
+
+        config.set_current_profile("personal".to_string());
+        assert_eq!(config.previous_profile, Some("work".to_string()));
+
+        // Re-applying the same profile keeps the previous one intact
+        config.set_current_profile("personal".to_string());
+        assert_eq!(config.previous_profile, Some("work".to_string()));
+    }
+
+    #[test]
+    fn test_replace_managed_profiles() {
+        let mut config = Config::default();
+        config.add_profile("mine".to_string(), identity("Me", "me@example.com"));
+        config.add_profile("work".to_string(), identity("Old Work", "old@corp.example"));
+
+        let mut bundle = HashMap::new();
+        bundle.insert("work".to_string(), identity("Work", "me@corp.example"));
+        bundle.insert("oss".to_string(), identity("OSS", "me@oss.example"));
+        bundle.insert("infra".to_string(), identity("Infra", "me@infra.example"));
+        assert_eq!(config.replace_managed_profiles(bundle), 2);
+        assert!(config.is_managed("oss"));
+        assert!(config.is_managed("infra"));
+        assert!(!config.is_managed("mine"));
+        // The user's own profile of the same name is kept
+        assert!(!config.is_managed("work"));
+        assert_eq!(config.get_profile("work").unwrap().email, "old@corp.example");
+
+        // A later bundle drops managed profiles it no longer lists, and references to them
+        config.current_profile = Some("oss".to_string());
+        config.previous_profile = Some("mine".to_string());
+        let mut bundle = HashMap::new();
+        bundle.insert("infra".to_string(), identity("Infra", "me@infra.example"));
+        assert_eq!(config.replace_managed_profiles(bundle), 1);
+        assert!(config.get_profile("oss").is_none());
+        assert!(config.get_profile("mine").is_some());
+        assert_eq!(config.current_profile, None);
+        assert_eq!(config.previous_profile.as_deref(), Some("mine"));
+    }
+
+    #[test]
+    fn test_recent_profiles_put_unused_last() {
+        let mut config = Config::default();
+        for (name, last_used) in [("alpha", None), ("beta", Some(10)), ("gamma", Some(20)), ("delta", None)] {
+            config.add_profile(name.to_string(), GitProfile { last_used, ..identity(name, "x@example.com") });
+        }
+        let names: Vec<&str> = config.recent_profiles().into_iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["gamma", "beta", "alpha", "delta"]);
+    }
+
+    #[test]
+    fn test_clean_config() {
+        let mut config = Config {
+            current_profile: Some("gone".to_string()),
+            previous_profile: Some("work".to_string()),
+            ..Default::default()
+        };
+        config.add_profile(
+            "work".to_string(),
+            GitProfile {
+                signing_key: Some("".to_string()),
+                ..identity(" Work User ", "work@example.com")
+            },
+        );
+
+        assert_eq!(
+            config.clean(),
+            [
+                "Removed current_profile 'gone', which names no profile",
+                "Trimmed whitespace around name of 'work'",
+                "Removed empty signing_key from 'work'",
+            ]
+        );
+        assert_eq!(config.current_profile, None);
+        assert_eq!(config.previous_profile.as_deref(), Some("work"));
+        assert_eq!(config.profiles["work"], identity("Work User", "work@example.com"));
+        assert!(config.clean().is_empty());
+    }
+
+    fn org_rule_config() -> Config {
+        let mut config = Config {
+            url_rules: vec![UrlRule {
+                pattern: "github.com[:/](?P<org>[^/]+)/".to_string(),
+                profile: Some("personal".to_string()),
+            }],
+            ..Default::default()
+        };
+        config.add_profile("acme".to_string(), identity("Acme Dev", "dev@acme.example"));
+        config.add_profile("personal".to_string(), identity("Me", "me@example.com"));
+        config
+    }
+
+    #[test]
+    fn test_url_rule_capture_names_profile() {
+        let config = org_rule_config();
+        let (profile, _) = config.match_url_rules("git@github.com:acme/app.git").unwrap();
+        assert_eq!(profile, "acme");
+    }
+
+    #[test]
+    fn test_url_rule_falls_back_to_rule_profile() {
+        let config = org_rule_config();
+        let (profile, pattern) = config.match_url_rules("https://github.com/someone/app.git").unwrap();
+        assert_eq!(profile, "personal");
+        assert_eq!(pattern, config.url_rules[0].pattern);
+        assert_eq!(config.match_url_rules("https://gitlab.com/acme/app.git"), None);
+    }
+
+    #[test]
+    fn test_invalid_url_rule_fails_to_load() {
+        with_test_config_env(|_config_dir| {
+            let path = Config::config_path().unwrap();
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "[profiles]\n\n[[url_rules]]\npattern = \"github.com/(unclosed\"\n").unwrap();
+
+            let err = Config::load().unwrap_err();
+            assert!(err.to_string().contains("Invalid url_rules pattern"));
+        });
+    }
+
+    #[test]
+    fn test_parse_lenient_skips_malformed_profile() {
+        let content = "current_profile = \"work\"\n\n\
+                       [profiles.work]\n\
+                       name = \"Work User\"\n\
+                       email = \"work@example.com\"\n\n\
+                       [profiles.broken]\n\
+                       name = \"Broken\"\n";
+        assert!(toml::from_str::<Config>(content).is_err());
+
+        let (config, errors) = Config::parse_lenient(content).unwrap();
+        assert_eq!(config.profiles.len(), 1);
+        assert_eq!(config.profiles["work"].email, "work@example.com");
+        assert_eq!(config.current_profile.as_deref(), Some("work"));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("broken: "));
+        assert!(errors[0].contains("email"));
+    }
+
     #[test]
     fn test_get_nonexistent_profile() {
         let config = Config::default();
@@ -185,6 +966,7 @@ mod tests {
                 name: "Test User".to_string(),
                 email: "test@example.com".to_string(),
                 signing_key: Some("ABC123".to_string()),
+                ..Default::default()
             };
             
             config.add_profile("test".to_string(), profile.clone());
@@ -205,6 +987,20 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_no_save_skips_writing() {
+        with_test_config_env(|_config_dir| {
+            let mut config = Config {
+                no_save: true,
+                ..Default::default()
+            };
+            config.add_profile("work".to_string(), identity("Work", "work@example.com"));
+            config.save().unwrap();
+
+            assert!(!Config::config_path().unwrap().exists());
+        });
+    }
+
     #[test]
     fn test_load_nonexistent_config() {
         with_test_config_env(|_config_dir| {
@@ -214,4 +1010,270 @@ mod tests {
             assert!(config.current_profile.is_none());
         });
     }
+
+    #[test]
+    fn test_config_path_honours_gswitch_env() {
+        with_test_config_env(|config_dir| {
+            unsafe {
+                std::env::set_var("GSWITCH_ENV", "work");
+            }
+            let path = Config::config_path();
+            unsafe {
+                std::env::remove_var("GSWITCH_ENV");
+            }
+            assert_eq!(path.unwrap(), config_dir.join("gswitch/config.work.toml"));
+            assert_eq!(Config::config_path().unwrap(), config_dir.join("gswitch/config.toml"));
+        });
+    }
+
+    fn identity(name: &str, email: &str) -> GitProfile {
+        GitProfile {
+            name: name.to_string(),
+            email: email.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_duplicate_identity_groups() {
+        let mut config = Config::default();
+        config.add_profile("work".to_string(), identity("Test User", "test@work.com"));
+        config.add_profile("acme".to_string(), identity("Test User", "test@work.com"));
+        config.add_profile("personal".to_string(), identity("Test User", "test@home.com"));
+
+        let mut signed = identity("Test User", "test@work.com");
+        signed.signing_key = Some("ABC123".to_string());
+        config.add_profile("work-signed".to_string(), signed);
+
+        assert_eq!(
+            config.duplicate_identity_groups(),
+            vec![vec!["acme".to_string(), "work".to_string()]]
+        );
+    }
+
+    #[test]
+    fn test_dedupe_keeper_selection() {
+        let group = vec!["acme".to_string(), "job".to_string(), "work".to_string()];
+
+        assert_eq!(dedupe_keeper(&group, None), "acme");
+        assert_eq!(dedupe_keeper(&group, Some("work")), "work");
+        // A --keep name outside the group falls back to the first name
+        assert_eq!(dedupe_keeper(&group, Some("personal")), "acme");
+    }
+
+    #[test]
+    fn test_merge_profile_into_updates_references() {
+        let mut config = Config::default();
+        config.add_profile("work".to_string(), identity("Test User", "test@work.com"));
+        config.add_profile("acme".to_string(), identity("Test User", "test@work.com"));
+        config.set_current_profile("work".to_string());
+
+        config.profiles.get_mut("work").unwrap().aliases = vec!["job".to_string()];
+        config.url_rules = vec![UrlRule { pattern: "acme".to_string(), profile: Some("work".to_string()) }];
+
+        config.merge_profile_into("work", "acme");
+
+        assert!(config.get_profile("work").is_none());
+        assert_eq!(config.current_profile, Some("acme".to_string()));
+        assert_eq!(config.url_rules[0].profile.as_deref(), Some("acme"));
+        assert_eq!(config.canonical_name("job"), "acme");
+    }
+
+    #[test]
+    fn test_find_profile_matching() {
+        let mut config = Config::default();
+        config.add_profile("work".to_string(), identity("Test User", "test@work.com"));
+        config.add_profile("personal".to_string(), identity("Test User", "test@home.com"));
+
+        assert_eq!(
+            config.find_profile_matching(&identity("Test User", "test@work.com")),
+            Some("work".to_string())
+        );
+        assert_eq!(config.find_profile_matching(&identity("Someone Else", "test@work.com")), None);
+    }
+
+    #[test]
+    fn test_normalize_signing_key_trims_whitespace() {
+        assert_eq!(normalize_signing_key("  ABC123DEF  \n", None, false).unwrap(), "ABC123DEF");
+    }
+
+    #[test]
+    fn test_normalize_signing_key_strips_0x_prefix() {
+        assert_eq!(normalize_signing_key("0xABC123DEF", None, false).unwrap(), "ABC123DEF");
+        assert_eq!(normalize_signing_key("0XABC123DEF!", None, false).unwrap(), "ABC123DEF!");
+        assert_eq!(normalize_signing_key("0xABC123DEF", None, true).unwrap(), "0xABC123DEF");
+    }
+
+    #[test]
+    fn test_normalize_signing_key_rejects_invalid_gpg_key() {
+        let result = normalize_signing_key("not-a-key!", Some("openpgp"), false);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("not a valid GPG key id"));
+    }
+
+    #[test]
+    fn test_normalize_signing_key_leaves_ssh_keys_alone() {
+        assert_eq!(
+            normalize_signing_key(" ~/.ssh/id_ed25519.pub ", Some("ssh"), false).unwrap(),
+            "~/.ssh/id_ed25519.pub"
+        );
+        assert_eq!(
+            normalize_signing_key("/home/me/keys/0xkey.pub", None, false).unwrap(),
+            "/home/me/keys/0xkey.pub"
+        );
+        assert_eq!(normalize_signing_key("user@example.com", None, false).unwrap(), "user@example.com");
+    }
+
+    #[test]
+    fn test_validate_branch_name() {
+        assert!(validate_branch_name("main").is_ok());
+        assert!(validate_branch_name("release/v1").is_ok());
+        assert!(validate_branch_name("").is_err());
+        assert!(validate_branch_name("has space").is_err());
+        assert!(validate_branch_name("a..b").is_err());
+        assert!(validate_branch_name("-main").is_err());
+    }
+
+    #[test]
+    fn test_get_profile_by_alias() {
+        let mut config = Config::default();
+        let work = GitProfile { aliases: vec!["acme".to_string(), "job".to_string()], ..identity("W", "w@work.com") };
+        config.add_profile("work".to_string(), work);
+
+        assert_eq!(config.canonical_name("job"), "work");
+        assert_eq!(config.get_profile("acme").unwrap().email, "w@work.com");
+        assert_eq!(config.canonical_name("other"), "other");
+        assert!(config.get_profile("other").is_none());
+    }
+
+    #[test]
+    fn test_alias_collision() {
+        let mut config = Config::default();
+        let work = GitProfile { aliases: vec!["acme".to_string()], ..identity("W", "w@work.com") };
+        config.add_profile("work".to_string(), work);
+        config.add_profile("personal".to_string(), identity("P", "p@home.com"));
+
+        let aliases = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        assert!(config.alias_collision("oss", &aliases(&["acme"])).unwrap().contains("alias of 'work'"));
+        assert!(config.alias_collision("oss", &aliases(&["personal"])).unwrap().contains("already a profile name"));
+        assert!(config.alias_collision("acme", &[]).unwrap().contains("alias of 'work'"));
+        // Re-saving a profile with its own aliases is fine
+        assert_eq!(config.alias_collision("work", &aliases(&["acme", "job"])), None);
+        assert_eq!(config.alias_collision("oss", &aliases(&["opensource"])), None);
+    }
+
+    #[test]
+    fn test_rename_profile_updates_references() {
+        let mut config = Config::default();
+        config.add_profile("work".to_string(), identity("W", "w@work.com"));
+        config.add_profile("personal".to_string(), identity("P", "p@home.com"));
+        config.current_profile = Some("work".to_string());
+        config.previous_profile = Some("personal".to_string());
+        config.url_rules = vec![
+            UrlRule { pattern: "github.com/acme/".to_string(), profile: Some("work".to_string()) },
+            UrlRule { pattern: "github.com/me/".to_string(), profile: Some("personal".to_string()) },
+        ];
+
+        config.rename_profile("work", "acme").unwrap();
+
+        assert!(config.get_profile("work").is_none());
+        assert_eq!(config.get_profile("acme").unwrap().email, "w@work.com");
+        assert_eq!(config.current_profile.as_deref(), Some("acme"));
+        assert_eq!(config.previous_profile.as_deref(), Some("personal"));
+        assert_eq!(config.url_rules[0].profile.as_deref(), Some("acme"));
+        assert_eq!(config.url_rules[1].profile.as_deref(), Some("personal"));
+
+        assert!(config.rename_profile("acme", "personal").is_err());
+        assert!(config.rename_profile("missing", "other").is_err());
+    }
+
+    #[test]
+    fn test_is_orphan() {
+        let mut config = Config::default();
+        for name in ["current", "ruled", "dotfile", "unused"] {
+            config.add_profile(name.to_string(), identity(name, "x@example.com"));
+        }
+        config.profiles.get_mut("dotfile").unwrap().aliases = vec!["df".to_string()];
+        config.current_profile = Some("current".to_string());
+        config.url_rules = vec![UrlRule { pattern: "acme".to_string(), profile: Some("ruled".to_string()) }];
+        let referenced = vec!["df".to_string()];
+
+        let orphans: Vec<&str> = config
+            .sorted_profiles()
+            .into_iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| config.is_orphan(name, &referenced))
+            .collect();
+        assert_eq!(orphans, vec!["unused"]);
+    }
+
+    #[test]
+    fn test_sorted_profiles_respects_order() {
+        let mut config = Config::default();
+        config.add_profile("alpha".to_string(), identity("A", "a@example.com"));
+        config.add_profile("zulu".to_string(), GitProfile { order: Some(1), ..identity("Z", "z@example.com") });
+        config.add_profile("mike".to_string(), GitProfile { order: Some(2), ..identity("M", "m@example.com") });
+        config.add_profile("bravo".to_string(), identity("B", "b@example.com"));
+
+        let names: Vec<&str> = config.sorted_profiles().into_iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["zulu", "mike", "alpha", "bravo"]);
+    }
+
+    #[test]
+    fn test_export_lines_include_custom_env() {
+        let mut profile = identity("Test User", "test@example.com");
+        profile.env.insert("HTTPS_PROXY".to_string(), "http://proxy.corp:8080".to_string());
+        profile.env.insert("CORP_ID".to_string(), "a b".to_string());
+
+        assert_eq!(
+            profile.export_lines(),
+            vec![
+                "export GIT_AUTHOR_NAME='Test User'",
+                "export GIT_AUTHOR_EMAIL=test@example.com",
+                "export GIT_COMMITTER_NAME='Test User'",
+                "export GIT_COMMITTER_EMAIL=test@example.com",
+                "export CORP_ID='a b'",
+                "export HTTPS_PROXY=http://proxy.corp:8080",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_email() {
+        assert!(validate_email("test@example.com").is_ok());
+        assert!(validate_email("dev+client@agency.co.uk").is_ok());
+        assert!(validate_email("Test User").is_err());
+        assert!(validate_email("test@localhost").is_err());
+        assert!(validate_email("a@b@example.com").is_err());
+        assert!(validate_email("has space@example.com").is_err());
+    }
+
+    #[test]
+    fn test_validate_clean_config() {
+        let mut config = Config::default();
+        config.add_profile("work".to_string(), identity("Test User", "test@work.com"));
+        config.set_current_profile("work".to_string());
+
+        assert!(config.validate(false).is_empty());
+    }
+
+    #[test]
+    fn test_validate_broken_config() {
+        let mut config = Config::default();
+        config.add_profile("work".to_string(), identity("Test User", "test@work.com"));
+        config.add_profile("acme".to_string(), identity("Test User", "test@work.com"));
+        config.add_profile("typo".to_string(), identity(" ", "not-an-email"));
+        config.set_current_profile("gone".to_string());
+
+        let problems = config.validate(false);
+        assert_eq!(
+            problems,
+            vec![
+                Problem::new("gone", "current_profile", "references a profile that doesn't exist"),
+                Problem::new("typo", "name", "is empty"),
+                Problem::new("typo", "email", "'not-an-email' is not a valid email address"),
+                Problem::new("work", "identity", "duplicates profile 'acme'"),
+            ]
+        );
+    }
 }
\ No newline at end of file
@@ -1,34 +1,76 @@
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use anyhow::{Context, Result};
-use crate::git;
+use crate::{git, system};
 
 const DOTFILE_NAME: &str = ".gswitch";
+/// Prefix of a .gswitch line that only applies on one machine
+const HOST_PREFIX: &str = "host:";
 
+/// The configured `dotfile_subpath`, set once the config is loaded
+static SUBPATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Also look for a dotfile at `subpath` below each directory searched
+pub fn set_subpath(subpath: impl Into<PathBuf>) {
+    let _ = SUBPATH.set(subpath.into());
+}
+
+/// The configured dotfile subpath, if any
+pub fn subpath() -> Option<&'static Path> {
+    SUBPATH.get().map(PathBuf::as_path)
+}
+
+/// The paths checked for a dotfile in `dir`, in order. The configured subpath is
+/// the more specific location, so it wins over a `.gswitch` in the same directory.
+pub fn dotfile_candidates(dir: &Path, subpath: Option<&Path>) -> Vec<PathBuf> {
+    subpath
+        .map(|subpath| dir.join(subpath))
+        .into_iter()
+        .chain([dir.join(DOTFILE_NAME)])
+        .collect()
+}
+
+/// The dotfile in `dir` itself, if any.
+/// is_file() follows symlinks, so a linked dotfile counts if its target is a file.
+fn dotfile_at(dir: &Path, subpath: Option<&Path>) -> Option<PathBuf> {
+    dotfile_candidates(dir, subpath).into_iter().find(|path| path.is_file())
+}
+
+/// The dotfile directly in `dir` (e.g. `~/.gswitch`), without walking up or
+/// requiring a git repository
+pub fn dotfile_in(dir: &Path) -> Option<PathBuf> {
+    dotfile_at(dir, subpath())
+}
 
 pub fn find_dotfile_in_dir<P: AsRef<Path>>(start_dir: Option<P>) -> Option<PathBuf> {
+    find_dotfile_with_subpath(start_dir, subpath())
+}
+
+fn find_dotfile_with_subpath<P: AsRef<Path>>(start_dir: Option<P>, subpath: Option<&Path>) -> Option<PathBuf> {
     let current_dir = if let Some(dir) = start_dir {
         dir.as_ref().to_path_buf()
     } else {
         std::env::current_dir().ok()?
     };
     
-    // Early exit: Check if .gswitch exists in current directory first (most common case)
-    let dotfile_path = current_dir.join(DOTFILE_NAME);
-    if dotfile_path.exists() {
+    // Early exit: Check if a dotfile exists in current directory first (most common case)
+    if let Some(dotfile_path) = dotfile_at(&current_dir, subpath) {
         // Still need to verify we're in a git repo for the file to be valid
-        if git::get_git_repo_info(Some(&current_dir)).is_some() {
+        if git::find_repo_root_in_dir(Some(&current_dir)).is_some() {
             return Some(dotfile_path);
         }
     }
     
     // Combined git check and root finding in one call
-    let git_root = git::get_git_repo_info(Some(&current_dir))?;
-    let mut search_dir = current_dir;
+    let git_root = git::find_repo_root_in_dir(Some(&current_dir))?;
+
+    // Compare real paths so a symlinked cwd or repo still stops at the git root
+    let git_root = git_root.canonicalize().unwrap_or(git_root);
+    let mut search_dir = current_dir.canonicalize().unwrap_or(current_dir);
     
     // Only search within the git repository boundaries
     loop {
-        let dotfile_path = search_dir.join(DOTFILE_NAME);
-        if dotfile_path.exists() {
+        if let Some(dotfile_path) = dotfile_at(&search_dir, subpath) {
             return Some(dotfile_path);
         }
         
@@ -41,28 +83,254 @@ pub fn find_dotfile_in_dir<P: AsRef<Path>>(start_dir: Option<P>) -> Option<PathB
     None
 }
 
+/// Continue the search past the repo root containing `dir`, returning the nearest
+/// .gswitch in an ancestor of the root. Only applies to repos under `stop` (usually
+/// `$HOME`), and never looks above it.
+pub fn find_dotfile_above_root<P: AsRef<Path>>(dir: Option<P>, stop: &Path) -> Option<PathBuf> {
+    let root = git::find_repo_root_in_dir(dir)?;
+    let root = root.canonicalize().unwrap_or(root);
+    let stop = stop.canonicalize().unwrap_or_else(|_| stop.to_path_buf());
+    if !root.starts_with(&stop) {
+        return None;
+    }
+
+    root.ancestors()
+        .skip(1)
+        .take_while(|ancestor| ancestor.starts_with(&stop))
+        .find_map(|ancestor| dotfile_at(ancestor, subpath()))
+}
+
+/// What a .gswitch file asks for: the repo's own profile and, with a
+/// `global <profile>` line, the profile to make the global default
+#[derive(Debug, PartialEq, Default)]
+pub struct DotfileSpec {
+    pub local: Option<String>,
+    pub global: Option<String>,
+    /// Set by an `only-dotfile` line: resolve from this file alone, ignoring
+    /// `gswitch.profile` and `url_rules`
+    pub only_dotfile: bool,
+}
+
+/// The .gswitch line that makes the file the only source of the repo's profile
+const ONLY_DOTFILE: &str = "only-dotfile";
+
 pub fn read_profile_from_dotfile<P: AsRef<Path>>(dotfile_path: P) -> Result<String> {
+    read_dotfile_spec(dotfile_path)?
+        .local
+        .context(".gswitch file only names a global profile")
+}
+
+pub fn read_dotfile_spec<P: AsRef<Path>>(dotfile_path: P) -> Result<DotfileSpec> {
     let content = std::fs::read_to_string(dotfile_path)
         .context("Failed to read .gswitch file")?;
-    
-    let profile_name = content.trim().to_string();
-    
-    if profile_name.is_empty() {
+
+    // Only look the hostname up when the file has per-host lines
+    let hostname = if content.contains(HOST_PREFIX) { system::hostname() } else { None };
+    parse_dotfile(&content, hostname.as_deref())
+}
+
+/// The profile name after a `local` or `global` keyword, if `line` starts with it
+fn directive<'a>(line: &'a str, keyword: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix(keyword)?;
+    rest.starts_with(char::is_whitespace).then(|| rest.trim())
+}
+
+/// Split .gswitch content into its local and global profiles. `local <profile>`
+/// is the same as a bare profile line, so single-line files keep working.
+pub fn parse_dotfile(content: &str, hostname: Option<&str>) -> Result<DotfileSpec> {
+    let mut global = None;
+    let mut only_dotfile = false;
+    let mut local_lines = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line == ONLY_DOTFILE {
+            only_dotfile = true;
+            continue;
+        }
+        match directive(line, "global") {
+            Some(name) => global = Some(validate_profile_name(name)?),
+            None => local_lines.push(directive(line, "local").unwrap_or(line)),
+        }
+    }
+
+    let local_content = local_lines.join("\n");
+    let local = if (global.is_some() || only_dotfile) && local_content.trim().is_empty() {
+        None
+    } else {
+        Some(select_profile(&local_content, hostname)?)
+    };
+    Ok(DotfileSpec { local, global, only_dotfile })
+}
+
+/// Pick the profile from .gswitch content. A `host:<hostname> <profile>` line
+/// matching this machine wins; otherwise the bare profile line is the fallback.
+pub fn select_profile(content: &str, hostname: Option<&str>) -> Result<String> {
+    let mut fallback = Vec::new();
+    let mut has_host_lines = false;
+    for line in content.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let Some(rule) = line.strip_prefix(HOST_PREFIX) else {
+            fallback.push(line);
+            continue;
+        };
+        has_host_lines = true;
+        let Some((host, profile_name)) = rule.split_once(char::is_whitespace) else {
+            anyhow::bail!(".gswitch line {:?} needs a profile after the hostname", line);
+        };
+        if let Some(hostname) = hostname
+            && system::hostname_matches(host, hostname)
+        {
+            return validate_profile_name(profile_name.trim());
+        }
+    }
+
+    if fallback.is_empty() {
+        if has_host_lines {
+            anyhow::bail!(
+                ".gswitch file has no line for host {} and no fallback profile",
+                hostname.unwrap_or("(unknown)")
+            );
+        }
         anyhow::bail!(".gswitch file is empty");
     }
-    
-    Ok(profile_name)
+    validate_profile_name(&fallback.join("\n"))
+}
+
+fn validate_profile_name(profile_name: &str) -> Result<String> {
+    // Profile names are single path-free tokens, so these mean a corrupted file
+    if let Some(bad) = profile_name.chars().find(|c| matches!(c, '/' | '\\' | '\n' | '\r')) {
+        anyhow::bail!(
+            ".gswitch file names an invalid profile {:?}: profile names can't contain {:?}",
+            profile_name,
+            bad
+        );
+    }
+
+    Ok(profile_name.to_string())
+}
+
+/// Recursively collect every .gswitch file (and file at the configured subpath)
+/// under `root`, skipping `.git`
+/// directories and symlinked directories. Results are sorted by path.
+pub fn find_dotfiles_under<P: AsRef<Path>>(root: P) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut pending = vec![root.as_ref().to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let name = entry.file_name();
+            if file_type.is_dir() {
+                if name != ".git" {
+                    pending.push(entry.path());
+                }
+            } else if name == DOTFILE_NAME || SUBPATH.get().is_some_and(|subpath| entry.path().ends_with(subpath)) {
+                found.push(entry.path());
+            }
+        }
+    }
+
+    found.sort();
+    found
+}
+
+/// The profile one .gswitch line names, whichever directive it uses (a bare
+/// profile, `local`, `global` or `host:<hostname>`). It's always the line's last word.
+fn line_profile(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.is_empty() || line == ONLY_DOTFILE {
+        return None;
+    }
+    let line = directive(line, "global").or_else(|| directive(line, "local")).unwrap_or(line);
+    match line.strip_prefix(HOST_PREFIX) {
+        Some(rule) => rule.split_once(char::is_whitespace).map(|(_, name)| name.trim()),
+        None => Some(line),
+    }
+}
+
+/// Every profile .gswitch `content` names, on any line and for any host
+pub fn named_profiles(content: &str) -> Vec<&str> {
+    content.lines().filter_map(line_profile).collect()
+}
+
+/// Rewrite the lines of .gswitch `content` that name a profile `matches` accepts,
+/// to name `replacement` instead or, without one, dropping them. Every other line
+/// is kept as written.
+fn rewrite_profile_lines(content: &str, matches: impl Fn(&str) -> bool, replacement: Option<&str>) -> String {
+    content
+        .split_inclusive('\n')
+        .filter_map(|line| {
+            let Some(name) = line_profile(line).filter(|name| matches(name)) else {
+                return Some(line.to_string());
+            };
+            let replacement = replacement?;
+            let end = line.trim_end().len();
+            Some(format!("{}{}{}", &line[..end - name.len()], replacement, &line[end..]))
+        })
+        .collect()
+}
+
+/// .gswitch files under `root` with a line naming a profile `matches` accepts
+pub fn find_dotfiles_naming<P: AsRef<Path>>(root: P, matches: impl Fn(&str) -> bool) -> Vec<PathBuf> {
+    find_dotfiles_under(root)
+        .into_iter()
+        .filter(|path| {
+            std::fs::read_to_string(path).is_ok_and(|content| named_profiles(&content).into_iter().any(&matches))
+        })
+        .collect()
+}
+
+/// Point the lines of the .gswitch at `path` that name a profile `matches` accepts
+/// at `replacement`, leaving the rest of the file alone. Returns whether any did.
+pub fn replace_profile_lines<P: AsRef<Path>>(path: P, matches: impl Fn(&str) -> bool, replacement: &str) -> Result<bool> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path).context("Failed to read .gswitch file")?;
+    let rewritten = rewrite_profile_lines(&content, matches, Some(replacement));
+    if rewritten == content {
+        return Ok(false);
+    }
+    std::fs::write(path, rewritten).context("Failed to write .gswitch file")?;
+    Ok(true)
+}
+
+/// Drop the lines of the .gswitch at `path` that name a profile `matches` accepts,
+/// deleting the file once no profile line is left. Returns whether it was deleted.
+pub fn remove_profile_lines<P: AsRef<Path>>(path: P, matches: impl Fn(&str) -> bool) -> Result<bool> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path).context("Failed to read .gswitch file")?;
+    let rewritten = rewrite_profile_lines(&content, matches, None);
+    if named_profiles(&rewritten).is_empty() {
+        std::fs::remove_file(path).with_context(|| format!("Failed to delete {}", path.display()))?;
+        return Ok(true);
+    }
+    std::fs::write(path, rewritten).context("Failed to write .gswitch file")?;
+    Ok(false)
 }
 
 pub fn create_dotfile<P: AsRef<Path>>(path: P, profile_name: &str) -> Result<()> {
-    std::fs::write(path, format!("{}\n", profile_name))
-        .context("Failed to create .gswitch file")
+    write_dotfile(path, profile_name, true)
+}
+
+/// Write a .gswitch file, with or without the trailing newline
+pub fn write_dotfile<P: AsRef<Path>>(path: P, profile_name: &str, trailing_newline: bool) -> Result<()> {
+    let content = if trailing_newline {
+        format!("{}\n", profile_name)
+    } else {
+        profile_name.to_string()
+    };
+    std::fs::write(path, content).context("Failed to create .gswitch file")
 }
 
-pub fn get_dotfile_profile() -> Option<String> {
-    get_dotfile_profile_in_dir(None::<&Path>)
+/// Whether an existing .gswitch file ends with a newline; `None` if it's missing or empty
+pub fn has_trailing_newline<P: AsRef<Path>>(path: P) -> Option<bool> {
+    let content = std::fs::read(path).ok()?;
+    content.last().map(|byte| *byte == b'\n')
 }
 
+#[allow(dead_code)]
 pub fn get_dotfile_profile_in_dir<P: AsRef<Path>>(start_dir: Option<P>) -> Option<String> {
     let dotfile_path = find_dotfile_in_dir(start_dir)?;
     read_profile_from_dotfile(dotfile_path).ok()
@@ -101,6 +369,30 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_find_dotfile_at_configured_subpath() {
+        with_git_repo(|repo| {
+            let subpath = Path::new(".config/gswitch/profile");
+            repo.create_dir("packages/app/.config/gswitch").unwrap();
+            repo.create_file("packages/app/.config/gswitch/profile", "app\n").unwrap();
+            repo.create_file(".gswitch", "root\n").unwrap();
+            let src = repo.create_dir("packages/app/src").unwrap();
+
+            // The package's subpath dotfile is closer than the root .gswitch
+            let found = find_dotfile_with_subpath(Some(&src), Some(subpath)).unwrap();
+            assert_path_eq!(found, repo.join("packages/app/.config/gswitch/profile"));
+
+            // In the same directory, the subpath is the more specific file
+            repo.create_file("packages/app/.gswitch", "other\n").unwrap();
+            let found = find_dotfile_with_subpath(Some(&src), Some(subpath)).unwrap();
+            assert_path_eq!(found, repo.join("packages/app/.config/gswitch/profile"));
+
+            // Without a configured subpath only .gswitch files count
+            let found = find_dotfile_with_subpath(Some(&src), None).unwrap();
+            assert_path_eq!(found, repo.join("packages/app/.gswitch"));
+        });
+    }
+
     #[test]
     fn test_find_dotfile_not_in_git_repo() {
         with_temp_dir(|temp_dir| {
@@ -122,6 +414,42 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_find_dotfile_above_root() {
+        with_temp_dir(|temp_dir| {
+            let repo = temp_dir.create_dir("work/repo").unwrap();
+            let output = std::process::Command::new("git").arg("init").current_dir(&repo).output().unwrap();
+            assert!(output.status.success());
+            temp_dir.create_file("work/.gswitch", "work\n").unwrap();
+
+            // The bounded search stops at the repo root
+            assert_eq!(find_dotfile_in_dir(Some(&repo)), None);
+
+            let found = find_dotfile_above_root(Some(&repo), temp_dir.path()).unwrap();
+            assert_path_eq!(found, temp_dir.join("work/.gswitch"));
+
+            // Nothing above the stop directory is considered
+            assert_eq!(find_dotfile_above_root(Some(&repo), &repo.join("..").join("repo")), None);
+        });
+    }
+
+    #[test]
+    fn test_write_dotfile_newline_variants() {
+        with_temp_dir(|temp_dir| {
+            let path = temp_dir.join(".gswitch");
+            assert_eq!(has_trailing_newline(&path), None);
+
+            create_dotfile(&path, "work").unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "work\n");
+            assert_eq!(has_trailing_newline(&path), Some(true));
+
+            write_dotfile(&path, "work", false).unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "work");
+            assert_eq!(has_trailing_newline(&path), Some(false));
+            assert_eq!(read_profile_from_dotfile(&path).unwrap(), "work");
+        });
+    }
+
     #[test]
     fn test_read_profile_from_dotfile() {
         with_temp_dir(|temp_dir| {
@@ -153,6 +481,81 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_read_profile_rejects_path_separators() {
+        with_temp_dir(|temp_dir| {
+            let gswitch_path = temp_dir.create_file(".gswitch", "work/extra\n").unwrap();
+
+            let err = read_profile_from_dotfile(&gswitch_path).unwrap_err();
+            assert!(err.to_string().contains("invalid profile"));
+            assert!(err.to_string().contains("'/'"));
+        });
+    }
+
+    #[test]
+    fn test_read_profile_rejects_multiple_lines() {
+        with_temp_dir(|temp_dir| {
+            let gswitch_path = temp_dir.create_file(".gswitch", "work\npersonal\n").unwrap();
+
+            let err = read_profile_from_dotfile(&gswitch_path).unwrap_err();
+            assert!(err.to_string().contains("invalid profile"));
+        });
+    }
+
+    #[test]
+    fn test_select_profile_by_hostname() {
+        let content = "host:work-laptop work\nhost:home-desktop personal\noss\n";
+        assert_eq!(select_profile(content, Some("work-laptop")).unwrap(), "work");
+        assert_eq!(select_profile(content, Some("home-desktop.local")).unwrap(), "personal");
+
+        // No host line matches, so the bare line is the fallback
+        assert_eq!(select_profile(content, Some("build-server")).unwrap(), "oss");
+        assert_eq!(select_profile(content, None).unwrap(), "oss");
+    }
+
+    #[test]
+    fn test_parse_dotfile_local_and_global() {
+        let spec = parse_dotfile("local work\nglobal personal\n", None).unwrap();
+        let expected = DotfileSpec {
+            local: Some("work".to_string()),
+            global: Some("personal".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(spec, expected);
+
+        let spec = parse_dotfile("global personal\n", None).unwrap();
+        assert_eq!(spec, DotfileSpec { global: Some("personal".to_string()), ..Default::default() });
+
+        // Plain files and a profile that happens to be called `local` still work
+        let spec = parse_dotfile("work\n", None).unwrap();
+        assert_eq!(spec, DotfileSpec { local: Some("work".to_string()), ..Default::default() });
+        assert_eq!(parse_dotfile("local\n", None).unwrap().local.as_deref(), Some("local"));
+
+        let spec = parse_dotfile("host:work-laptop work\nlocal oss\nglobal personal\n", Some("work-laptop")).unwrap();
+        assert_eq!(spec.local.as_deref(), Some("work"));
+        assert!(parse_dotfile("global a/b\n", None).is_err());
+    }
+
+    #[test]
+    fn test_parse_dotfile_only_dotfile() {
+        let spec = parse_dotfile("work\nonly-dotfile\n", None).unwrap();
+        assert_eq!(spec, DotfileSpec { local: Some("work".to_string()), only_dotfile: true, ..Default::default() });
+
+        let spec = parse_dotfile("only-dotfile\n", None).unwrap();
+        assert_eq!(spec, DotfileSpec { only_dotfile: true, ..Default::default() });
+        assert!(!parse_dotfile("work\n", None).unwrap().only_dotfile);
+    }
+
+    #[test]
+    fn test_select_profile_without_fallback() {
+        let content = "host:work-laptop work\n";
+        let err = select_profile(content, Some("build-server")).unwrap_err();
+        assert!(err.to_string().contains("no line for host build-server"));
+
+        let err = select_profile("host:work-laptop\n", Some("work-laptop")).unwrap_err();
+        assert!(err.to_string().contains("needs a profile"));
+    }
+
     #[test]
     fn test_read_profile_from_whitespace_only_dotfile() {
         with_temp_dir(|temp_dir| {
@@ -218,4 +621,98 @@ mod tests {
             assert!(profile_name.is_none());
         });
     }
+
+    #[test]
+    fn test_find_dotfiles_under() {
+        with_temp_dir(|temp_dir| {
+            temp_dir.create_file("work/api/.gswitch", "work\n").unwrap();
+            temp_dir.create_file("personal/.gswitch", "personal\n").unwrap();
+            temp_dir.create_file("work/api/.git/.gswitch", "ignored\n").unwrap();
+            temp_dir.create_file("work/notes.txt", "no dotfile here\n").unwrap();
+
+            let found = find_dotfiles_under(temp_dir.path());
+            assert_eq!(
+                found,
+                vec![temp_dir.join("personal/.gswitch"), temp_dir.join("work/api/.gswitch")]
+            );
+        });
+    }
+
+    #[test]
+    fn test_find_dotfiles_naming() {
+        with_temp_dir(|temp_dir| {
+            temp_dir.create_file("a/.gswitch", "work\n").unwrap();
+            temp_dir.create_file("b/.gswitch", "personal\n").unwrap();
+            temp_dir.create_file("c/d/.gswitch", "  work  \n").unwrap();
+
+            temp_dir.create_file("e/.gswitch", "host:laptop personal\nglobal work\n").unwrap();
+            temp_dir.create_file("f/.gswitch", "host:build-01 job\npersonal\n").unwrap();
+
+            let found = find_dotfiles_naming(temp_dir.path(), |name| name == "work");
+            assert_eq!(
+                found,
+                vec![temp_dir.join("a/.gswitch"), temp_dir.join("c/d/.gswitch"), temp_dir.join("e/.gswitch")]
+            );
+            // Lines for other machines count too
+            let found = find_dotfiles_naming(temp_dir.path(), |name| name == "job");
+            assert_eq!(found, vec![temp_dir.join("f/.gswitch")]);
+        });
+    }
+
+    #[test]
+    fn test_named_profiles_covers_every_directive() {
+        let content = "only-dotfile\nhost:laptop  work\nlocal personal\nglobal home\n\n";
+        assert_eq!(named_profiles(content), ["work", "personal", "home"]);
+    }
+
+    #[test]
+    fn test_rewrite_profile_lines_keeps_the_rest() {
+        with_temp_dir(|temp_dir| {
+            let path = temp_dir.create_file(".gswitch", "host:laptop old\nhost:build-01 ci\nglobal old\npersonal\n").unwrap();
+            assert!(!remove_profile_lines(&path, |name| name == "old").unwrap());
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "host:build-01 ci\npersonal\n");
+
+            assert!(replace_profile_lines(&path, |name| name == "ci", "build").unwrap());
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "host:build-01 build\npersonal\n");
+            assert!(!replace_profile_lines(&path, |name| name == "ci", "build").unwrap());
+
+            // Once nothing names a profile, the file goes
+            assert!(remove_profile_lines(&path, |name| name == "build" || name == "personal").unwrap());
+            assert!(!path.exists());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_dotfile_through_symlinked_directory() {
+        with_git_repo(|repo| {
+            repo.create_file(".gswitch", "test-profile\n").unwrap();
+            let deep = repo.create_dir("real/deep").unwrap();
+
+            with_temp_dir(|outside| {
+                // Enter the repo through a symlink that lives outside it
+                let link = outside.join("link");
+                std::os::unix::fs::symlink(&deep, &link).unwrap();
+
+                let dotfile_path = find_dotfile_in_dir(Some(&link));
+                assert_path_eq!(dotfile_path.unwrap(), repo.join(".gswitch"));
+            });
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_symlinked_dotfile_reads_target() {
+        with_git_repo(|repo| {
+            let target = repo.create_file("profiles/shared", "shared-profile\n").unwrap();
+            std::os::unix::fs::symlink(&target, repo.join(".gswitch")).unwrap();
+
+            let profile_name = get_dotfile_profile_in_dir(Some(repo.path()));
+            assert_eq!(profile_name, Some("shared-profile".to_string()));
+
+            // A dangling link is ignored rather than treated as a dotfile
+            std::fs::remove_file(&target).unwrap();
+            assert!(find_dotfile_in_dir(Some(repo.path())).is_none());
+        });
+    }
 }
\ No newline at end of file
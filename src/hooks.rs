@@ -0,0 +1,115 @@
+use std::path::Path;
+use anyhow::{Context, Result};
+
+/// Git hooks that can change which profile applies: checking out another branch
+/// and merging changes to `.gswitch`
+pub const HOOK_NAMES: &[&str] = &["post-checkout", "post-merge"];
+
+const BEGIN_MARKER: &str = "# >>> gswitch >>>";
+const END_MARKER: &str = "# <<< gswitch <<<";
+
+/// The managed region: run `gsw auto` quietly, never failing the git command
+fn managed_block() -> String {
+    format!(
+        "{}\ncommand -v gsw >/dev/null 2>&1 && gsw auto >/dev/null || true\n{}\n",
+        BEGIN_MARKER, END_MARKER
+    )
+}
+
+/// Append the managed region to a hook's content, or start a new script.
+/// Returns `None` when the region is already present.
+pub fn add_managed_block(existing: Option<&str>) -> Option<String> {
+    match existing {
+        Some(content) if content.contains(BEGIN_MARKER) => None,
+        Some(content) if !content.trim().is_empty() => {
+            let separator = if content.ends_with('\n') { "" } else { "\n" };
+            Some(format!("{}{}\n{}", content, separator, managed_block()))
+        }
+        _ => Some(format!("#!/bin/sh\n\n{}", managed_block())),
+    }
+}
+
+/// Strip the managed region from a hook's content, leaving the rest untouched.
+/// Returns `None` when there was no region to remove.
+pub fn remove_managed_block(content: &str) -> Option<String> {
+    let start = content.find(BEGIN_MARKER)?;
+    let end = content[start..].find(END_MARKER)? + start + END_MARKER.len();
+    let before = content[..start].trim_end_matches('\n');
+    let after = content[end..].trim_start_matches('\n');
+    let mut remaining = before.to_string();
+    if !after.is_empty() {
+        remaining.push_str("\n\n");
+        remaining.push_str(after);
+    } else if !remaining.is_empty() {
+        remaining.push('\n');
+    }
+    Some(remaining)
+}
+
+/// Whether a hook has nothing left to run besides its shebang
+pub fn is_empty_hook(content: &str) -> bool {
+    content.lines().all(|line| line.trim().is_empty() || line.starts_with("#!"))
+}
+
+/// Add the managed region to `hook`, creating it executable if needed.
+/// Returns whether the file changed.
+pub fn install(hook: &Path) -> Result<bool> {
+    let existing = std::fs::read_to_string(hook).ok();
+    let Some(content) = add_managed_block(existing.as_deref()) else {
+        return Ok(false);
+    };
+    if let Some(parent) = hook.parent() {
+        std::fs::create_dir_all(parent).context("Failed to create hooks directory")?;
+    }
+    std::fs::write(hook, content).with_context(|| format!("Failed to write {}", hook.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = std::fs::metadata(hook)?.permissions();
+        permissions.set_mode(permissions.mode() | 0o755);
+        std::fs::set_permissions(hook, permissions)
+            .with_context(|| format!("Failed to make {} executable", hook.display()))?;
+    }
+    Ok(true)
+}
+
+/// Remove the managed region from `hook`, deleting the file if nothing else is left.
+/// Returns whether the file changed.
+pub fn uninstall(hook: &Path) -> Result<bool> {
+    let Ok(content) = std::fs::read_to_string(hook) else {
+        return Ok(false);
+    };
+    let Some(remaining) = remove_managed_block(&content) else {
+        return Ok(false);
+    };
+    if is_empty_hook(&remaining) {
+        std::fs::remove_file(hook).with_context(|| format!("Failed to remove {}", hook.display()))?;
+    } else {
+        std::fs::write(hook, remaining).with_context(|| format!("Failed to write {}", hook.display()))?;
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_and_remove_managed_block() {
+        let existing = "#!/bin/sh\necho existing\n";
+        let installed = add_managed_block(Some(existing)).unwrap();
+        assert!(installed.starts_with(existing));
+        assert!(installed.contains("gsw auto"));
+        assert_eq!(add_managed_block(Some(&installed)), None);
+
+        assert_eq!(remove_managed_block(&installed).unwrap(), existing);
+        assert_eq!(remove_managed_block(existing), None);
+    }
+
+    #[test]
+    fn test_new_hook_is_empty_after_removal() {
+        let installed = add_managed_block(None).unwrap();
+        assert!(installed.starts_with("#!/bin/sh\n"));
+        assert!(is_empty_hook(&remove_managed_block(&installed).unwrap()));
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+use crate::config::GitProfile;
+use crate::git;
+
+/// A directory pinned to a profile by a .gswitch file
+pub struct DirectoryRule {
+    pub directory: PathBuf,
+    pub profile: String,
+}
+
+/// Path of the include file generated for a profile
+pub fn include_file_path(include_dir: &Path, profile: &str) -> PathBuf {
+    include_dir.join(format!("{}.gitconfig", profile))
+}
+
+/// Render `[includeIf "gitdir:..."]` blocks, one per directory rule
+pub fn render_includeif_blocks(rules: &[DirectoryRule], include_dir: &Path) -> String {
+    let mut output = String::new();
+    for rule in rules {
+        let mut gitdir = rule.directory.display().to_string();
+        if !gitdir.ends_with('/') {
+            gitdir.push('/');
+        }
+        output.push_str(&format!("[includeIf \"gitdir:{}\"]\n", gitdir));
+        output.push_str(&format!(
+            "\tpath = {}\n",
+            quote_config_value(&include_file_path(include_dir, &rule.profile).display().to_string())
+        ));
+    }
+    output
+}
+
+/// Render the per-profile include file carrying every setting the profile defines
+pub fn render_profile_include(profile: &GitProfile) -> String {
+    // Group keys by section, keeping sections in the order they first appear
+    let mut sections: Vec<(&str, Vec<(&str, String)>)> = Vec::new();
+    for (key, value) in git::profile_settings(profile) {
+        let (Some(value), Some((section, name))) = (value, key.split_once('.')) else {
+            continue;
+        };
+        match sections.iter_mut().find(|(existing, _)| *existing == section) {
+            Some((_, entries)) => entries.push((name, value)),
+            None => sections.push((section, vec![(name, value)])),
+        }
+    }
+
+    let mut output = String::new();
+    for (section, entries) in sections {
+        output.push_str(&format!("[{}]\n", section));
+        for (name, value) in entries {
+            output.push_str(&format!("\t{} = {}\n", name, quote_config_value(&value)));
+        }
+    }
+    output
+}
+
+/// Quote a git config value when it contains characters git would otherwise mangle
+fn quote_config_value(value: &str) -> String {
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', ';', '"', '\\']);
+    if !needs_quotes {
+        return value.to_string();
+    }
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_includeif_blocks_one_per_rule() {
+        let rules = vec![
+            DirectoryRule { directory: PathBuf::from("/home/me/work"), profile: "work".to_string() },
+            DirectoryRule { directory: PathBuf::from("/home/me/oss/"), profile: "oss".to_string() },
+        ];
+
+        let output = render_includeif_blocks(&rules, Path::new("/home/me/.config/gswitch/includes"));
+
+        assert!(output.contains("[includeIf \"gitdir:/home/me/work/\"]\n\tpath = /home/me/.config/gswitch/includes/work.gitconfig\n"));
+        assert!(output.contains("[includeIf \"gitdir:/home/me/oss/\"]\n\tpath = /home/me/.config/gswitch/includes/oss.gitconfig\n"));
+    }
+
+    #[test]
+    fn test_render_profile_include() {
+        let profile = GitProfile {
+            name: "Test User".to_string(),
+            email: "test@example.com".to_string(),
+            signing_key: Some("ABC123".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render_profile_include(&profile),
+            "[user]\n\tname = Test User\n\temail = test@example.com\n\tsigningkey = ABC123\n"
+        );
+    }
+
+    #[test]
+    fn test_render_profile_include_groups_sections() {
+        let profile = GitProfile {
+            name: "Test User".to_string(),
+            email: "test@example.com".to_string(),
+            signing_format: Some("ssh".to_string()),
+            require_explicit: Some(true),
+            pull_rebase: Some("true".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render_profile_include(&profile),
+            "[user]\n\tname = Test User\n\temail = test@example.com\n\tuseConfigOnly = true\n\
+             [gpg]\n\tformat = ssh\n\
+             [pull]\n\trebase = true\n"
+        );
+    }
+
+    #[test]
+    fn test_quote_config_value() {
+        assert_eq!(quote_config_value("plain value"), "plain value");
+        assert_eq!(quote_config_value("a # b"), "\"a # b\"");
+        assert_eq!(quote_config_value("say \"hi\""), "\"say \\\"hi\\\"\"");
+    }
+}
@@ -0,0 +1,230 @@
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use anyhow::{Context, Result, bail};
+
+/// Expand a leading `~/` to the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Whether a signing key is a literal public key rather than a key file
+pub fn is_literal_ssh_key(key: &str) -> bool {
+    key.starts_with("ssh-") || key.starts_with("key::")
+}
+
+/// Whether a signing key looks like an SSH key (a key file or a literal public key)
+pub fn is_ssh_key(key: &str, format: Option<&str>) -> bool {
+    format == Some("ssh") || is_literal_ssh_key(key) || key.starts_with('~') || key.contains('/')
+}
+
+/// Reject a signing key that can't work with `format`: ssh signing needs a literal
+/// public key or a key file that exists, and GPG/X.509 can't use an SSH key
+pub fn check_key_matches_format(key: &str, format: &str) -> Result<()> {
+    if format == "ssh" {
+        if is_literal_ssh_key(key) || expand_home(key).is_file() {
+            return Ok(());
+        }
+        bail!("ssh signing needs a public key file or a literal ssh key, but '{}' is neither", key);
+    }
+    if is_ssh_key(key, None) {
+        bail!("'{}' looks like an SSH key, which {} signing can't use", key, format);
+    }
+    Ok(())
+}
+
+/// Check that a signing key can actually be used: SSH key files must exist,
+/// GPG keys must have a secret key in the keyring
+pub fn signing_key_available(key: &str, format: Option<&str>) -> bool {
+    if is_ssh_key(key, format) {
+        // Literal public keys are passed to ssh-keygen as-is
+        return is_literal_ssh_key(key) || expand_home(key).is_file();
+    }
+
+    let program = if format == Some("x509") { "gpgsm" } else { "gpg" };
+    Command::new(program)
+        .args(["--batch", "--list-secret-keys", key])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// Locate an executable on `PATH`
+pub fn find_program(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Create an ed25519 key pair at `~/.ssh/gswitch_<profile>` with ssh-keygen,
+/// returning the public key path. ssh-keygen prompts for the passphrase itself.
+pub fn generate_ssh_key(profile_name: &str, email: &str) -> Result<PathBuf> {
+    let program = find_program("ssh-keygen").context("ssh-keygen not found on PATH")?;
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    let private_key = home.join(".ssh").join(format!("gswitch_{}", profile_name));
+    if private_key.exists() {
+        bail!("{} already exists", private_key.display());
+    }
+    if let Some(parent) = private_key.parent() {
+        std::fs::create_dir_all(parent).context("Failed to create ~/.ssh")?;
+    }
+
+    let status = Command::new(program)
+        .args(["-t", "ed25519", "-C", email, "-f"])
+        .arg(&private_key)
+        .status()
+        .context("Failed to run ssh-keygen")?;
+    let public_key = PathBuf::from(format!("{}.pub", private_key.display()));
+    if !status.success() || !public_key.is_file() {
+        bail!("ssh-keygen did not create {}", public_key.display());
+    }
+    Ok(public_key)
+}
+
+/// Create a GPG key for `name <email>` and return its fingerprint.
+/// gpg asks for the passphrase through its usual pinentry.
+pub fn generate_gpg_key(name: &str, email: &str) -> Result<String> {
+    let program = find_program("gpg").context("gpg not found on PATH")?;
+    let user_id = format!("{} <{}>", name, email);
+
+    let status = Command::new(&program)
+        .args(["--quick-generate-key", &user_id, "default", "default", "never"])
+        .status()
+        .context("Failed to run gpg")?;
+    if !status.success() {
+        bail!("gpg failed to generate a key for {}", user_id);
+    }
+
+    let output = Command::new(&program)
+        .args(["--batch", "--with-colons", "--list-secret-keys", &user_id])
+        .output()
+        .context("Failed to run gpg")?;
+    secret_key_fingerprints(&String::from_utf8_lossy(&output.stdout))
+        .pop()
+        .with_context(|| format!("Could not find the generated key for {}", user_id))
+}
+
+/// Fingerprints of the primary secret keys in `gpg --with-colons` output, oldest first
+fn secret_key_fingerprints(colons: &str) -> Vec<String> {
+    let mut fingerprints = Vec::new();
+    let mut in_secret_key = false;
+    for line in colons.lines() {
+        let mut fields = line.split(':');
+        match fields.next() {
+            Some("sec") => in_secret_key = true,
+            Some("ssb") => in_secret_key = false,
+            Some("fpr") if in_secret_key => {
+                fingerprints.extend(fields.nth(8).map(str::to_string));
+                in_secret_key = false;
+            }
+            _ => {}
+        }
+    }
+    fingerprints
+}
+
+/// Find a signing key for `gsw import --detect-key` when git config has none:
+/// the only secret key in the GPG keyring, otherwise gpg.conf's `default-key`
+pub fn detect_signing_key() -> Option<String> {
+    if let Some(program) = find_program("gpg")
+        && let Ok(output) = Command::new(program)
+            .args(["--batch", "--with-colons", "--list-secret-keys"])
+            .stderr(Stdio::null())
+            .output()
+    {
+        let mut fingerprints = secret_key_fingerprints(&String::from_utf8_lossy(&output.stdout));
+        if fingerprints.len() == 1 {
+            return fingerprints.pop();
+        }
+    }
+
+    let gnupg_home = std::env::var_os("GNUPGHOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".gnupg")))?;
+    let gpg_conf = std::fs::read_to_string(gnupg_home.join("gpg.conf")).ok()?;
+    parse_default_key(&gpg_conf)
+}
+
+/// The `default-key` option from gpg.conf; gpg uses the last one when repeated
+fn parse_default_key(gpg_conf: &str) -> Option<String> {
+    gpg_conf
+        .lines()
+        .rev()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.strip_prefix("default-key"))
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+        .map(|rest| rest.trim().to_string())
+        .find(|key| !key.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_is_ssh_key() {
+        assert!(is_ssh_key("~/.ssh/id_ed25519.pub", None));
+        assert!(is_ssh_key("ssh-ed25519 AAAAC3Nza", None));
+        assert!(is_ssh_key("anything", Some("ssh")));
+        assert!(!is_ssh_key("ABC123DEF", None));
+        assert!(is_literal_ssh_key("key::ssh-ed25519 AAAAC3Nza"));
+        assert!(!is_literal_ssh_key("~/.ssh/id_ed25519.pub"));
+    }
+
+    #[test]
+    fn test_check_key_matches_format() {
+        with_temp_dir(|temp_dir| {
+            let key_file = temp_dir.create_file("id_ed25519.pub", "ssh-ed25519 AAAAC3Nza\n").unwrap();
+            let key_file = key_file.to_str().unwrap();
+
+            assert!(check_key_matches_format(key_file, "ssh").is_ok());
+            assert!(check_key_matches_format("ssh-ed25519 AAAAC3Nza", "ssh").is_ok());
+            assert!(check_key_matches_format("ABC123DEF", "ssh").is_err());
+            assert!(check_key_matches_format(&temp_dir.join("missing.pub").to_string_lossy(), "ssh").is_err());
+            assert!(check_key_matches_format("ABC123DEF", "openpgp").is_ok());
+            assert!(check_key_matches_format(key_file, "openpgp").is_err());
+        });
+    }
+
+    #[test]
+    fn test_secret_key_fingerprints() {
+        let colons = "sec:u:255:22:AAAA1111:1700000000:::u:::scESC:::+:::ed25519:::0:\n\
+                      fpr:::::::::OLDFINGERPRINT:\n\
+                      ssb:u:255:18:BBBB2222:1700000000::::::e:::+:::cv25519::\n\
+                      fpr:::::::::SUBKEYFINGERPRINT:\n\
+                      sec:u:255:22:CCCC3333:1710000000:::u:::scESC:::+:::ed25519:::0:\n\
+                      fpr:::::::::NEWFINGERPRINT:\n";
+        assert_eq!(secret_key_fingerprints(colons), ["OLDFINGERPRINT", "NEWFINGERPRINT"]);
+        assert!(secret_key_fingerprints("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_default_key() {
+        let gpg_conf = "# Options for GnuPG\n\
+                        keyid-format 0xlong\n\
+                        # default-key COMMENTEDOUT\n\
+                        default-keyserver hkps://keys.openpgp.org\n\
+                        default-key  0xABCDEF1234567890  \n\
+                        with-fingerprint\n";
+        assert_eq!(parse_default_key(gpg_conf), Some("0xABCDEF1234567890".to_string()));
+        assert_eq!(parse_default_key("keyid-format long\n"), None);
+    }
+
+    #[test]
+    fn test_ssh_key_file_availability() {
+        with_temp_dir(|temp_dir| {
+            let key = temp_dir.create_file("id_ed25519.pub", "ssh-ed25519 AAAA test\n").unwrap();
+            assert!(signing_key_available(&key.to_string_lossy(), Some("ssh")));
+
+            let missing = temp_dir.join("missing.pub");
+            assert!(!signing_key_available(&missing.to_string_lossy(), Some("ssh")));
+        });
+    }
+}
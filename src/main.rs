@@ -1,23 +1,58 @@
+mod cache;
 mod config;
 mod git;
 mod dotfile;
+mod hooks;
+mod includeif;
+mod keys;
+mod policy;
+mod remote;
+mod resolve;
+mod shell;
+mod sshconfig;
+mod system;
+mod warnings;
 
 #[cfg(test)]
 mod test_utils;
 
-use clap::{Parser, Subcommand};
-use anyhow::Result;
-use config::{Config, GitProfile};
+use clap::{CommandFactory, Parser, Subcommand};
+use anyhow::{Context, Result, bail};
+use config::{Config, GitProfile, dedupe_keeper, normalize_signing_key, validate_branch_name, validate_email};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use resolve::{ProfileSource, Resolution};
 
 #[derive(Parser)]
 #[command(name = "gsw")]
 #[command(about = "A CLI tool for switching git profiles")]
 struct Cli {
+    /// Log every git command run and its exit status to stderr
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// Exit nonzero when a command emits any warning
+    #[arg(long, global = true)]
+    strict: bool,
+    /// Don't write changes to config.toml (git config is still changed)
+    #[arg(long, global = true)]
+    no_save: bool,
+    /// Don't run profiles' on_switch commands
+    #[arg(long, global = true)]
+    no_hooks: bool,
+    /// Skip malformed profiles in config.toml instead of failing
+    #[arg(long, global = true)]
+    lenient: bool,
+    /// Run local, auto, current, init, import or prompt against the repo in this directory
+    #[arg(long, global = true, value_name = "PATH")]
+    dir: Option<PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
+// Parsed once per run, so the size of `Add` doesn't matter
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Add a new git profile
     Add {
@@ -32,196 +67,1332 @@ enum Commands {
         /// Git signing key (optional)
         #[arg(long)]
         signing_key: Option<String>,
+        /// Read the signing key from a file instead of the command line
+        #[arg(long, conflicts_with = "signing_key")]
+        signing_key_file: Option<PathBuf>,
+        /// Create a new signing key with gpg or ssh-keygen and use it
+        #[arg(long, value_parser = ["gpg", "ssh"], conflicts_with_all = ["signing_key", "signing_key_file"])]
+        generate_key: Option<String>,
+        /// Signing key format (openpgp, x509, ssh)
+        #[arg(long, value_parser = ["openpgp", "x509", "ssh"])]
+        signing_format: Option<String>,
+        /// Signing program (gpg.program, or gpg.ssh.program for ssh keys)
+        #[arg(long)]
+        signing_program: Option<String>,
+        /// Keep a leading 0x on GPG key ids instead of stripping it
+        #[arg(long = "keep-0x")]
+        keep_0x: bool,
+        /// Set user.useConfigOnly so git never guesses an identity
+        #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+        require_explicit: Option<bool>,
+        /// Set pull.rebase (true, false, merges, interactive)
+        #[arg(long, value_parser = ["true", "false", "merges", "interactive"])]
+        pull_rebase: Option<String>,
+        /// Set push.autoSetupRemote
+        #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+        push_auto_setup_remote: Option<bool>,
+        /// Set init.defaultBranch
+        #[arg(long)]
+        init_default_branch: Option<String>,
+        /// Set fetch.prune
+        #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+        fetch_prune: Option<bool>,
+        /// Set core.sshCommand
+        #[arg(long)]
+        ssh_command: Option<String>,
+        /// Set http.proxy, which git uses for https URLs too
+        #[arg(long)]
+        http_proxy: Option<String>,
+        /// Shell command run after switching to this profile
+        #[arg(long)]
+        on_switch: Option<String>,
+        /// Scope `gsw use` applies this profile at by default (local, global)
+        #[arg(long, value_parser = ["local", "global"])]
+        scope_default: Option<String>,
+        /// Remote host to sign commits for in `auto` and `local`; elsewhere signing is off (repeatable)
+        #[arg(long = "sign-host", value_name = "HOST")]
+        sign_hosts: Vec<String>,
+        /// Another name to accept for this profile when switching (repeatable)
+        #[arg(long = "alias", value_name = "NAME")]
+        aliases: Vec<String>,
+        /// Environment variable exported by `auto --export-env` (KEY=VALUE, repeatable)
+        #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_var)]
+        env: Vec<(String, String)>,
     },
     /// List all profiles
-    List,
+    List {
+        /// Mark the profile matching the global identity and the one resolved for this repo
+        #[arg(long)]
+        resolve: bool,
+        /// Order by when profiles were last applied, most recent first
+        #[arg(long)]
+        recent: bool,
+        /// Only show profiles whose name, user name or email contains this (case-insensitive)
+        #[arg(long)]
+        filter: Option<String>,
+        /// Print a JSON array of each profile's identity, use_count and last_used
+        #[arg(long)]
+        usage_json: bool,
+        /// Print `name<TAB>user_name<TAB>email<TAB>signing_key<TAB>current` lines for scripts
+        #[arg(long, conflicts_with = "usage_json")]
+        porcelain: bool,
+        /// Only show profiles nothing refers to: not current or previous, no url rule, no .gswitch under --root
+        #[arg(long, conflicts_with_all = ["usage_json", "porcelain"])]
+        orphans: bool,
+        /// Directory to scan for .gswitch files that keep a profile from being an orphan
+        #[arg(long, requires = "orphans")]
+        root: Option<PathBuf>,
+        /// Warn about signing keys missing from the keyring (GPG) or from disk (SSH)
+        #[arg(long, conflicts_with_all = ["usage_json", "porcelain", "orphans"])]
+        check_keys: bool,
+    },
     /// Remove a profile
     Remove {
         /// Profile name to remove
         name: String,
+        /// Report .gswitch files under this directory that still name the profile
+        #[arg(long)]
+        clean_dotfiles: Option<PathBuf>,
+        /// Delete the reported .gswitch files instead of only listing them
+        #[arg(long, requires = "clean_dotfiles")]
+        yes: bool,
     },
     /// Switch to a profile globally
     Switch {
-        /// Profile name to switch to
+        /// Profile name to switch to, or `-` for the previous profile
+        name: String,
+        /// Apply locally instead when run inside a git repository
+        #[arg(long)]
+        local_if_repo: bool,
+        /// Run this command under the profile's identity instead of switching
+        #[arg(long, num_args = 1.., allow_hyphen_values = true, value_name = "COMMAND", conflicts_with = "local_if_repo")]
+        then: Option<Vec<String>>,
+        /// Only apply these fields (name, email, signing_key), leaving the rest as-is
+        #[arg(long, value_delimiter = ',', value_parser = clap::builder::PossibleValuesParser::new(git::FIELDS), conflicts_with = "then")]
+        only: Option<Vec<String>>,
+        /// Show the identity git will use afterward and wait for Enter before switching
+        #[arg(long, conflicts_with = "then")]
+        confirm_identity: bool,
+        /// Skip the --confirm-identity prompt, e.g. in automation
+        #[arg(long, requires = "confirm_identity")]
+        yes: bool,
+    },
+    /// Apply a profile locally inside a repo and globally elsewhere
+    Use {
+        /// Profile name to apply
+        name: String,
+        /// Scope to apply at, overriding the profile's scope_default and repo detection
+        #[arg(long, value_parser = ["local", "global"])]
+        scope: Option<String>,
+    },
+    /// Compare a stored profile against the live git config, exiting nonzero on any difference
+    DiffLive {
+        /// Profile name to compare
+        name: String,
+        /// Only read this scope instead of the effective config (local, global)
+        #[arg(long, value_parser = ["local", "global"])]
+        scope: Option<String>,
+    },
+    /// Set a signing key in this repository's own config, outside any profile
+    PinKey {
+        /// Signing key (GPG key id, or SSH key path or literal)
+        key: String,
+        /// Provenance note recorded as gswitch.keyOrigin, e.g. who issued the key
+        #[arg(long)]
+        origin: Option<String>,
+    },
+    /// Apply a profile to a repository on another machine over ssh
+    RemoteApply {
+        /// Remote repository as user@host:path
+        target: String,
+        /// Profile name to apply
+        profile: String,
+    },
+    /// Apply a profile to a throwaway repository and print the git config it ends up with
+    TestApply {
+        /// Profile name to try
+        name: String,
+    },
+    /// Print the git commands applying a profile would run, without running them
+    ShowConfig {
+        /// Profile name to preview
+        name: String,
+        /// Scope the commands would write to (local, global)
+        #[arg(long, default_value = "local", value_parser = ["local", "global"])]
+        scope: String,
+    },
+    /// Print a profile as a config.toml table, or as a script for machines without gswitch
+    Export {
+        /// Profile name to export
         name: String,
+        /// Print a shell script of `git config --global` commands instead
+        #[arg(long)]
+        as_script: bool,
     },
     /// Switch to a profile locally (current repo only)
     Local {
         /// Profile name to switch to
-        name: String,
+        #[arg(required_unless_present = "detect")]
+        name: Option<String>,
+        /// Report which profile matches the repo's existing local identity instead
+        #[arg(long, conflicts_with = "name")]
+        detect: bool,
+        /// Pin the repo to the detected profile via its local gswitch.profile config
+        #[arg(long, requires = "detect")]
+        adopt: bool,
+        /// Apply the profile to every worktree of the repository
+        #[arg(long, conflicts_with = "detect")]
+        all_worktrees: bool,
+        /// Only apply these fields (name, email, signing_key), leaving the rest as-is
+        #[arg(long, value_delimiter = ',', value_parser = clap::builder::PossibleValuesParser::new(git::FIELDS), conflicts_with = "detect")]
+        only: Option<Vec<String>>,
+    },
+    /// Summarize the global and local identity and the profile resolved for this directory
+    Status {
+        /// Print the summary as a JSON object
+        #[arg(long)]
+        json: bool,
     },
     /// Show current git configuration
     Current {
         /// Output format (full, name, email)
         #[arg(long, default_value = "full")]
         format: String,
+        /// Marker shown before the full output when commits are signed
+        #[arg(long, default_value = "[signed]")]
+        signed_marker: String,
+        /// Colorize the signed marker (honours NO_COLOR when auto)
+        #[arg(long, value_enum, default_value_t = clap::ColorChoice::Auto)]
+        color: clap::ColorChoice,
+        /// Also name the saved profile matching the identity
+        #[arg(long)]
+        identify: bool,
+        /// Show the identity at every scope (system, global, local) next to the effective one
+        #[arg(long, value_parser = ["all"])]
+        scope: Option<String>,
+        /// Report the author recorded on this commit instead of the configured identity
+        #[arg(long, value_name = "REF", conflicts_with = "scope")]
+        from_commit: Option<String>,
     },
     /// Auto-switch based on .gswitch file
-    Auto,
+    Auto {
+        /// Append a line describing each resolution decision to this file
+        #[arg(long)]
+        log: Option<PathBuf>,
+        /// Print a single stable outcome token and exit with a matching code
+        #[arg(long)]
+        porcelain: bool,
+        /// Read the profile from this file instead of searching for .gswitch
+        #[arg(long, env = "GSWITCH_FILE")]
+        file: Option<PathBuf>,
+        /// Print `export` lines for the resolved identity and its custom env vars
+        #[arg(long, conflicts_with = "porcelain")]
+        export_env: bool,
+        /// Keep looking for .gswitch above the repo root, up to $HOME
+        #[arg(long)]
+        no_boundary: bool,
+        /// Write a local identity even when the global one already matches the profile
+        #[arg(long)]
+        always_local: bool,
+    },
     /// Create a .gswitch file in current directory
     Init {
-        /// Profile name to set in .gswitch file
-        profile: String,
+        /// Profile name to set in .gswitch file (defaults to the profile matching the repo's identity)
+        profile: Option<String>,
+        /// Write the profile to this file instead of ./.gswitch
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Write to this path relative to the current directory instead (e.g. the
+        /// configured dotfile_subpath), creating its parent directories
+        #[arg(long, value_name = "RELPATH", conflicts_with = "file")]
+        at: Option<PathBuf>,
+        /// Don't end the file with a newline
+        #[arg(long)]
+        no_newline: bool,
     },
     /// Import current git identity as a new profile
     Import {
         /// Profile name for the imported identity
         name: String,
+        /// Refresh an existing profile from the current identity instead of refusing
+        #[arg(long)]
+        update: bool,
+        /// Without user.signingkey, use the only GPG secret key or gpg.conf's default-key
+        #[arg(long)]
+        detect_key: bool,
+        /// Config scope to read the identity from; effective is what git would use here
+        #[arg(long, default_value = "effective", value_parser = ["global", "local", "effective"])]
+        scope: String,
+    },
+    /// Create skeleton profiles from the host aliases in ~/.ssh/config
+    ImportSshConfig {
+        /// SSH config to read instead of ~/.ssh/config
+        #[arg(long)]
+        ssh_config: Option<PathBuf>,
+        /// Create the profiles instead of only listing them
+        #[arg(long)]
+        yes: bool,
     },
     /// Generate shell integration script
     Activate {
         /// Shell type (bash, zsh, fish, nushell)
         shell: String,
+        /// Report whether the integration is loaded in this shell or set up in its rc file
+        #[arg(long)]
+        check: bool,
+    },
+    /// Print the gsw version
+    Version {
+        /// Also print the git version, enabled features and the config and state paths, for bug reports
+        #[arg(long)]
+        verbose: bool,
+    },
+    /// Generate the shell integration script for the shell in $SHELL
+    ShellInit,
+    /// Generate shell completions
+    Completions {
+        /// Shell to generate completions for (defaults to $SHELL)
+        shell: Option<clap_complete::Shell>,
+        /// Write the completions to the shell's conventional location
+        #[arg(long)]
+        install: bool,
+        /// Overwrite an existing completion file not written by gsw
+        #[arg(long, requires = "install")]
+        force: bool,
     },
     /// Get profile for prompt display (fast, optimized for shell prompts)
-    Prompt,
+    Prompt {
+        /// Read the profile from this file instead of ./.gswitch
+        #[arg(long, env = "GSWITCH_FILE")]
+        file: Option<PathBuf>,
+        /// Show the global current profile when no .gswitch file applies
+        #[arg(long)]
+        global_fallback: bool,
+        /// Print `profile=<name>` and a newline instead of ` <name>` for status bars
+        #[arg(long)]
+        kv: bool,
+        /// Exit 2 instead of 1 when there's no profile because this isn't a git repo
+        #[arg(long)]
+        distinguish: bool,
+        /// Give up and print nothing (exit 1) if reading the file takes longer than this
+        #[arg(long, value_name = "MS")]
+        timeout: Option<u64>,
+        /// Search up to the repo root like `auto`, instead of only the current directory
+        #[arg(long)]
+        walk: bool,
+        /// With --walk, keep searching above the repo root, up to $HOME
+        #[arg(long, requires = "walk")]
+        no_boundary: bool,
+    },
+    /// Set a profile's position in `gsw list`
+    Reorder {
+        /// Profile name to move
+        name: String,
+        /// Display position (lower comes first)
+        position: u32,
+    },
+    /// Rename a profile, updating the config's references to it
+    Rename {
+        /// Current profile name
+        old: String,
+        /// New profile name
+        new: String,
+    },
+    /// Targeted fixes to profiles
+    #[command(alias = "profiles")]
+    Profile {
+        #[command(subcommand)]
+        action: ProfileCommands,
+    },
+    /// Change only a profile's email, keeping its other settings
+    SetEmail {
+        /// Profile name to edit
+        name: String,
+        /// New email address
+        email: String,
+    },
+    /// Change a profile's signing key and format together, e.g. to move from GPG to SSH signing
+    SetSigning {
+        /// Profile name to edit
+        name: String,
+        /// Signing format the key is for
+        #[arg(long, value_parser = ["openpgp", "x509", "ssh"])]
+        format: String,
+        /// Signing key (GPG key id, or SSH public key path or literal)
+        #[arg(long)]
+        key: String,
+        /// Set gpg.ssh.allowedSignersFile (ssh format only)
+        #[arg(long)]
+        allowed_signers_file: Option<String>,
+    },
+    /// Re-apply the globally current profile so global git config picks up edits to it
+    Refresh,
+    /// Check every profile for problems, exiting nonzero if any are found
+    ValidateAll {
+        /// Print problems as a JSON array of {profile, field, problem}
+        #[arg(long)]
+        json: bool,
+        /// Also check that signing keys exist in the keyring or on disk
+        #[arg(long)]
+        check_keys: bool,
+    },
+    /// Include a gswitch-owned file from ~/.gitconfig and send global switches there
+    SetupGlobalInclude,
+    /// Check the config for problems; with --fix, repair the ones that have a safe fix
+    Doctor {
+        /// Clear dangling references, trim whitespace and create the config directory, then check again
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Clear all gswitch-managed git settings
+    Reset {
+        /// Scope to reset (local or global)
+        #[arg(long, value_parser = ["local", "global"])]
+        scope: Option<String>,
+    },
+    /// Open config.toml in $EDITOR, checking that it still parses afterwards
+    ConfigEdit,
+    /// Rewrite config.toml with profiles and their lists in sorted order
+    ProfilesSort,
+    /// Drop dangling references, stray whitespace and empty settings from config.toml
+    CleanConfig {
+        /// Only report what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Manage profiles distributed by an organization policy
+    Policy {
+        #[command(subcommand)]
+        action: PolicyCommands,
+    },
+    /// Report repos under a directory whose local identity doesn't match their profile
+    Reconcile {
+        /// Directory to scan for git repositories
+        root: PathBuf,
+        /// Switch drifted repos to their profile instead of only reporting them
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Install or remove git hooks that run `gsw auto` after checkouts and merges
+    Hooks {
+        /// install or uninstall
+        #[arg(value_parser = ["install", "uninstall"])]
+        action: String,
+    },
+    /// Translate .gswitch files into native git includeIf config
+    GenerateIncludeif {
+        /// Directory to scan for .gswitch files
+        #[arg(long, default_value = ".")]
+        root: PathBuf,
+        /// Write the includeIf snippet and per-profile include files to this directory
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfileCommands {
+    /// Swap a profile's user name and email, for one added with them transposed
+    SwapNameEmail {
+        /// Profile name to fix
+        name: String,
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Merge profiles that share the same name, email and signing key
+    Dedupe {
+        /// Only report what would be merged
+        #[arg(long)]
+        dry_run: bool,
+        /// Preferred profile to keep when it's part of a duplicate group
+        #[arg(long)]
+        keep: Option<String>,
+        /// Repoint .gswitch files under this directory (default: the current repository)
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum PolicyCommands {
+    /// Fetch the policy bundle and install its profiles as managed profiles
+    Sync {
+        /// Bundle URL to fetch, saved as the configured policy_url
+        #[arg(long)]
+        url: Option<String>,
+    },
+}
+
+impl Commands {
+    /// Whether the command reads or writes config.toml. The rest must keep working
+    /// when the config is missing, unreadable or corrupt.
+    fn needs_config(&self) -> bool {
+        !matches!(
+            self,
+            Commands::Activate { .. }
+                | Commands::ShellInit
+                | Commands::Version { .. }
+                | Commands::Completions { .. }
+                | Commands::Prompt { .. }
+                | Commands::Doctor { .. }
+        )
+    }
+
+    /// Whether the command honors the global `--dir`
+    fn supports_dir(&self) -> bool {
+        matches!(
+            self,
+            Commands::Local { .. }
+                | Commands::Auto { .. }
+                | Commands::Current { .. }
+                | Commands::Init { .. }
+                | Commands::Import { .. }
+                | Commands::Prompt { .. }
+        )
+    }
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    let mut config = Config::load()?;
+    if cli.verbose {
+        env_logger::Builder::new()
+            .filter_level(log::LevelFilter::Debug)
+            .format_timestamp(None)
+            .format_target(false)
+            .init();
+    }
+    if cli.dir.is_some() && !cli.command.supports_dir() {
+        bail!("--dir only applies to local, auto, current, init, import and prompt");
+    }
+    if !cli.command.needs_config() {
+        run_without_config(cli.command, cli.no_save, cli.dir.as_deref())?;
+        exit_on_warnings(cli.strict);
+        return Ok(());
+    }
+    let mut config = if cli.lenient {
+        let (mut config, errors) = Config::load_lenient()?;
+        for error in &errors {
+            warnings::warn(format!("Skipping invalid profile {}", error));
+        }
+        // Saving would silently drop the skipped profiles
+        if !errors.is_empty() {
+            warnings::warn("config.toml won't be saved until the invalid profiles are fixed");
+            config.no_save = true;
+        }
+        config
+    } else {
+        Config::load()?
+    };
+    config.no_save |= cli.no_save;
+    config.no_hooks = cli.no_hooks;
+    if let Some(subpath) = &config.dotfile_subpath {
+        dotfile::set_subpath(subpath);
+    }
+    if config.global_include {
+        git::set_global_file(&Config::global_include_path()?);
+    }
+
+    let strict = cli.strict || config.strict;
+
+    // A command's own error takes precedence over failing for warnings
+    run(cli.command, &mut config, cli.dir.as_deref())?;
+    exit_on_warnings(strict);
+    Ok(())
+}
 
-    match cli.command {
-        Commands::Add { name, user_name, email, signing_key } => {
+/// In strict mode, exit 1 if the command that just finished emitted any warnings
+fn exit_on_warnings(strict: bool) {
+    let warnings = warnings::take();
+    if strict && !warnings.is_empty() {
+        eprintln!("Failing because of {} warning(s) in strict mode", warnings.len());
+        std::process::exit(1);
+    }
+}
+
+/// `dir` is the global `--dir`: the repo the commands that support it act on
+/// instead of the current directory's
+fn run(command: Commands, config: &mut Config, dir: Option<&Path>) -> Result<()> {
+    match command {
+        Commands::Add {
+            name,
+            user_name,
+            email,
+            signing_key,
+            signing_key_file,
+            generate_key,
+            signing_format,
+            signing_program,
+            keep_0x,
+            require_explicit,
+            pull_rebase,
+            push_auto_setup_remote,
+            init_default_branch,
+            fetch_prune,
+            ssh_command,
+            http_proxy,
+            on_switch,
+            scope_default,
+            sign_hosts,
+            aliases,
+            env,
+        } => {
+            if config.is_managed(&name) {
+                println!("Profile '{}' is managed by policy and can't be edited", name);
+                return Ok(());
+            }
+            if let Some(collision) = config.alias_collision(&name, &aliases) {
+                println!("Can't add profile '{}': {}", name, collision);
+                return Ok(());
+            }
+            if let (Some(kind), Some(format)) = (&generate_key, &signing_format)
+                && format != if kind == "ssh" { "ssh" } else { "openpgp" }
+            {
+                println!("--generate-key {} can't be combined with --signing-format {}", kind, format);
+                return Ok(());
+            }
+            // The name becomes part of the key's file name under ~/.ssh
+            if generate_key.as_deref() == Some("ssh") && (name.contains(['/', '\\']) || name.contains("..")) {
+                println!("Can't generate an SSH key for '{}': the name can't contain '/', '\\' or '..'", name);
+                return Ok(());
+            }
+            if let Some(branch) = &init_default_branch {
+                validate_branch_name(branch)?;
+            }
+            // Validate everything before generating, so a rejected add leaves no key behind
+            let signing_key = signing_key_file
+                .map(|path| read_signing_key_file(&path))
+                .transpose()?
+                .or(signing_key)
+                .map(|key| normalize_signing_key(&key, signing_format.as_deref(), keep_0x))
+                .transpose()?;
+            let mut signing_format = signing_format;
+            let signing_key = match (signing_key, generate_key.as_deref()) {
+                (Some(key), _) => Some(key),
+                (None, Some("ssh")) => {
+                    let public_key = keys::generate_ssh_key(&name, &email)?;
+                    println!("Generated SSH signing key {}", public_key.display());
+                    signing_format = Some("ssh".to_string());
+                    Some(public_key.to_string_lossy().into_owned())
+                }
+                (None, Some(_)) => {
+                    let fingerprint = keys::generate_gpg_key(&user_name, &email)?;
+                    println!("Generated GPG signing key {}", fingerprint);
+                    Some(fingerprint)
+                }
+                (None, None) => None,
+            };
             let profile = GitProfile {
                 name: user_name,
                 email,
                 signing_key,
+                signing_format,
+                signing_program,
+                require_explicit,
+                pull_rebase,
+                push_auto_setup_remote,
+                init_default_branch,
+                fetch_prune,
+                ssh_command,
+                http_proxy,
+                on_switch,
+                scope_default,
+                sign_hosts,
+                aliases,
+                env: env.into_iter().collect(),
+                ..Default::default()
             };
+            let duplicate = config
+                .profiles
+                .iter()
+                .filter(|(key, other)| **key != name && other.name == profile.name && other.email == profile.email)
+                .map(|(key, _)| key.clone())
+                .min();
             config.add_profile(name.clone(), profile);
             config.save()?;
             println!("Profile '{}' added successfully", name);
+            if let Some(duplicate) = duplicate {
+                warnings::warn(format!("Profile '{}' has the same identity as '{}'", name, duplicate));
+            }
         }
 
-        Commands::List => {
-            if config.profiles.is_empty() {
+        Commands::List { resolve, recent, filter, usage_json, porcelain, orphans, root, check_keys } => {
+            let machine = usage_json || porcelain;
+            if config.profiles.is_empty() && !machine {
                 println!("No profiles configured");
                 return Ok(());
             }
 
+            let (global_identity, repo_profile) = if resolve {
+                let global = git::get_scoped_git_config_in_dir("--global", None::<&Path>).ok();
+                let repo = match resolve::resolve_profile_in_dir(None, None::<&Path>) {
+                    Ok(Resolution::Found(name, _)) => Some(config.canonical_name(&name).to_string()),
+                    _ => None,
+                };
+                (global, repo)
+            } else {
+                (None, None)
+            };
+
+            let mut profiles = if recent { config.recent_profiles() } else { config.sorted_profiles() };
+            if let Some(filter) = &filter {
+                let filter = filter.to_lowercase();
+                profiles.retain(|(name, profile)| {
+                    [name.as_str(), &profile.name, &profile.email]
+                        .iter()
+                        .any(|field| field.to_lowercase().contains(&filter))
+                });
+                if profiles.is_empty() && !machine {
+                    println!("No matching profiles");
+                    return Ok(());
+                }
+            }
+
+            if orphans {
+                let mut referenced = Vec::new();
+                if let Some(root) = &root {
+                    for path in dotfile::find_dotfiles_under(root) {
+                        if let Ok(spec) = dotfile::read_dotfile_spec(&path) {
+                            referenced.extend(spec.local.into_iter().chain(spec.global));
+                        }
+                    }
+                }
+                profiles.retain(|(name, _)| config.is_orphan(name, &referenced));
+                if profiles.is_empty() {
+                    println!("No orphaned profiles");
+                    return Ok(());
+                }
+                println!("Orphaned profiles:");
+                for (name, profile) in profiles {
+                    println!("  {} - {} <{}>", name, profile.name, profile.email);
+                }
+                return Ok(());
+            }
+
+            if usage_json {
+                let usage: Vec<serde_json::Value> = profiles
+                    .iter()
+                    .map(|(name, profile)| {
+                        serde_json::json!({
+                            "name": name,
+                            "user_name": profile.name,
+                            "email": profile.email,
+                            "signing_key": profile.signing_key,
+                            "use_count": profile.use_count,
+                            "last_used": profile.last_used,
+                        })
+                    })
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&usage)?);
+                return Ok(());
+            }
+
+            // Scripts cut these by position, so the column order must never change
+            if porcelain {
+                for (name, profile) in profiles {
+                    let current = config.current_profile.as_ref() == Some(name);
+                    println!(
+                        "{}\t{}\t{}\t{}\t{}",
+                        name,
+                        profile.name,
+                        profile.email,
+                        profile.signing_key.as_deref().unwrap_or_default(),
+                        if current { 1 } else { 0 }
+                    );
+                }
+                return Ok(());
+            }
+
             println!("Available profiles:");
-            for (name, profile) in &config.profiles {
+            for (name, profile) in profiles {
                 let current = if config.current_profile.as_ref() == Some(name) {
                     " (current)"
                 } else {
                     ""
                 };
-                println!("  {} - {} <{}>{}", name, profile.name, profile.email, current);
+                let mut markers = String::new();
+                if let Some(global) = &global_identity
+                    && global.name == profile.name
+                    && global.email == profile.email
+                {
+                    markers.push_str(" (global)");
+                }
+                if repo_profile.as_ref() == Some(name) {
+                    markers.push_str(" (repo)");
+                }
+                if profile.managed {
+                    markers.push_str(" (managed)");
+                }
+                println!("  {} - {} <{}>{}{}", name, profile.name, profile.email, current, markers);
                 if let Some(key) = &profile.signing_key {
                     println!("    Signing key: {}", key);
+                    if check_keys && !keys::signing_key_available(key, profile.signing_format.as_deref()) {
+                        println!("    Signing key missing");
+                        warnings::warn(format!("Profile '{}' can't sign: key {} not found", name, key));
+                    }
+                }
+                if let Some(format) = &profile.signing_format {
+                    println!("    Signing format: {}", format);
+                }
+                if let Some(program) = &profile.signing_program {
+                    println!("    Signing program: {}", program);
+                }
+                if profile.require_explicit == Some(true) {
+                    println!("    Requires explicit identity");
+                }
+                if let Some(rebase) = &profile.pull_rebase {
+                    println!("    pull.rebase: {}", rebase);
+                }
+                if let Some(auto_setup) = profile.push_auto_setup_remote {
+                    println!("    push.autoSetupRemote: {}", auto_setup);
+                }
+                if let Some(branch) = &profile.init_default_branch {
+                    println!("    init.defaultBranch: {}", branch);
+                }
+                if let Some(prune) = profile.fetch_prune {
+                    println!("    fetch.prune: {}", prune);
+                }
+                if let Some(command) = &profile.ssh_command {
+                    println!("    core.sshCommand: {}", command);
+                }
+                if let Some(proxy) = &profile.http_proxy {
+                    println!("    http.proxy: {}", proxy);
+                }
+                if let Some(command) = &profile.on_switch {
+                    println!("    On switch: {}", command);
+                }
+                if let Some(scope) = &profile.scope_default {
+                    println!("    Default scope: {}", scope);
                 }
             }
         }
 
-        Commands::Remove { name } => {
-            if config.remove_profile(&name) {
-                config.save()?;
-                println!("Profile '{}' removed successfully", name);
-            } else {
+        Commands::Remove { name, clean_dotfiles, yes } => {
+            if config.is_managed(&name) {
+                println!("Profile '{}' is managed by policy and can't be removed", name);
+                return Ok(());
+            }
+            // A .gswitch may still use one of the profile's aliases
+            let aliases = config.get_profile(&name).map(|profile| profile.aliases.clone()).unwrap_or_default();
+            if !config.remove_profile(&name) {
                 println!("Profile '{}' not found", name);
+                return Ok(());
+            }
+            config.save()?;
+            println!("Profile '{}' removed successfully", name);
+
+            if let Some(root) = clean_dotfiles {
+                let names_removed = |named: &str| named == name || aliases.iter().any(|alias| alias == named);
+                let dangling = dotfile::find_dotfiles_naming(&root, names_removed);
+                if dangling.is_empty() {
+                    println!("No .gswitch files under {} reference '{}'", root.display(), name);
+                }
+                for path in dangling {
+                    if yes {
+                        if dotfile::remove_profile_lines(&path, names_removed)? {
+                            println!("Deleted {}", path.display());
+                        } else {
+                            println!("Removed the lines naming '{}' from {}", name, path.display());
+                        }
+                    } else {
+                        println!("Still references '{}': {}", name, path.display());
+                    }
+                }
             }
         }
 
-        Commands::Switch { name } => {
+        Commands::Switch { name, local_if_repo, then, only, confirm_identity, yes } => {
+            let name = if name == "-" {
+                let Some(previous) = config.previous_profile.clone() else {
+                    println!("No previous profile to switch back to");
+                    return Ok(());
+                };
+                previous
+            } else {
+                config.canonical_name(&name).to_string()
+            };
+
             if let Some(profile) = config.get_profile(&name) {
-                git::set_git_config(profile, true)?;
-                config.set_current_profile(name.clone());
+                if let Some(command) = then {
+                    profile.check_complete()?;
+                    let status = std::process::Command::new(&command[0])
+                        .args(&command[1..])
+                        .envs(git::identity_env(profile))
+                        .status()
+                        .with_context(|| format!("Failed to run {}", command[0]))?;
+                    std::process::exit(status.code().unwrap_or(1));
+                }
+
+                let local = local_if_repo && git::is_git_repo();
+                if confirm_identity && !confirm_resulting_identity(profile, local, only.as_deref(), yes)? {
+                    println!("Switch cancelled");
+                    return Ok(());
+                }
+
+                if local {
+                    git::set_git_config_in_dir(profile, false, None::<&Path>, only.as_deref())?;
+                    git::apply_sign_hosts_in_dir(profile, None::<&Path>)?;
+                    config.mark_used(&name);
+                    config.save()?;
+                    println!("Switched to profile '{}' locally", name);
+                    after_switch(config, &name);
+                    return Ok(());
+                }
+
+                git::set_git_config_in_dir(profile, true, None::<&Path>, only.as_deref())?;
+                // A partial switch doesn't make the profile the global identity
+                if only.is_none() {
+                    config.set_current_profile(name.clone());
+                }
+                config.mark_used(&name);
                 config.save()?;
                 println!("Switched to profile '{}' globally", name);
+                after_switch(config, &name);
             } else {
                 println!("Profile '{}' not found", name);
             }
         }
 
-        Commands::Local { name } => {
-            if !git::is_git_repo() {
-                println!("Not in a git repository");
+        Commands::Use { name, scope } => {
+            let name = config.canonical_name(&name).to_string();
+            let Some(profile) = config.get_profile(&name) else {
+                println!("Profile '{}' not found", name);
                 return Ok(());
-            }
+            };
+            let in_repo = git::is_git_repo();
+            let scope = scope
+                .or_else(|| profile.scope_default.clone())
+                .unwrap_or_else(|| if in_repo { "local" } else { "global" }.to_string());
 
-            if let Some(profile) = config.get_profile(&name) {
+            if scope == "local" {
+                if !in_repo {
+                    println!("Not in a git repository");
+                    return Ok(());
+                }
                 git::set_git_config(profile, false)?;
+                git::apply_sign_hosts_in_dir(profile, None::<&Path>)?;
+                config.mark_used(&name);
+                config.save()?;
                 println!("Switched to profile '{}' locally", name);
+                after_switch(config, &name);
             } else {
-                println!("Profile '{}' not found", name);
+                git::set_git_config(profile, true)?;
+                config.set_current_profile(name.clone());
+                config.mark_used(&name);
+                config.save()?;
+                println!("Switched to profile '{}' globally", name);
+                after_switch(config, &name);
             }
         }
 
-        Commands::Current { format } => {
-            match git::get_current_git_config() {
-                Ok(profile) => {
-                    match format.as_str() {
-                        "name" => println!("{}", profile.name),
-                        "email" => println!("{}", profile.email),
-                        "full" => {
-                            println!("Current git configuration:");
-                            println!("  Name: {}", profile.name);
-                            println!("  Email: {}", profile.email);
-                            if let Some(key) = profile.signing_key {
-                                println!("  Signing key: {}", key);
-                            }
-                        }
-                        _ => {
-                            println!("Invalid format: {}. Valid formats: full, name, email", format);
-                            return Ok(());
-                        }
-                    }
-                }
-                Err(e) => {
-                    if format.as_str() == "full" {
-                        println!("Failed to get current git configuration: {}", e);
-                    }
-                    // Silent for name/email format when there's an error
-                }
+        Commands::TestApply { name } => {
+            let Some(profile) = config.get_profile(&name) else {
+                println!("Profile '{}' not found", name);
+                return Ok(());
+            };
+            let stamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |since| since.as_nanos());
+            let repo = std::env::temp_dir().join(format!("gswitch-test-apply-{}-{}", std::process::id(), stamp));
+            std::fs::create_dir_all(&repo).context("Failed to create a temporary directory")?;
+
+            let applied = git::init_repo(&repo).and_then(|()| git::set_git_config_in_dir(profile, false, Some(&repo), None));
+            let settings: Vec<(&str, String)> = git::MANAGED_KEYS
+                .iter()
+                .filter_map(|key| git::get_scoped_config_value_in_dir(Some("--local"), key, Some(&repo)).map(|value| (*key, value)))
+                .collect();
+            let _ = std::fs::remove_dir_all(&repo);
+            applied.with_context(|| format!("Applying '{}' to a test repository failed", name))?;
+
+            println!("Applying '{}' to a new repository sets:", name);
+            for (key, value) in settings {
+                println!("  {} = {}", key, value);
             }
         }
 
-        Commands::Auto => {
-            // Early exit: Check for .gswitch file first (fastest check)
-            let Some(profile_name) = dotfile::get_dotfile_profile() else {
-                return Ok(()); // Silent exit when no .gswitch file - this is normal
+        Commands::ShowConfig { name, scope } => {
+            let Some(profile) = config.get_profile(&name) else {
+                println!("Profile '{}' not found", name);
+                return Ok(());
             };
-
-            // Early exit: Only proceed if in git repo
-            if !git::is_git_repo() {
-                return Ok(()); // Silent exit when not in git repo
+            let scope = format!("--{}", scope);
+            let clearable = git::clearable_keys_in_dir(&scope, None::<&Path>);
+            for change in git::planned_changes(profile, clearable.as_deref()) {
+                let args: Vec<String> = change.git_args(&scope).iter().map(|arg| shell::quote(arg)).collect();
+                println!("git {}", args.join(" "));
             }
+        }
 
-            // Check if we have the profile in config
-            let Some(profile) = config.get_profile(&profile_name) else {
-                eprintln!("Profile '{}' specified in .gswitch file not found", profile_name);
+        Commands::Export { name, as_script } => {
+            let name = config.canonical_name(&name).to_string();
+            let Some(profile) = config.get_profile(&name) else {
+                println!("Profile '{}' not found", name);
                 return Ok(());
             };
-
-            // Check if we're already using the correct profile locally
-            if let Ok(current_profile) = git::get_current_git_config() {
-                if current_profile.email == profile.email && current_profile.name == profile.name {
-                    return Ok(()); // Already using correct profile, no need to switch
+            if as_script {
+                println!("#!/bin/sh");
+                println!("# Applies the gswitch profile '{}' to the global git config", name);
+                println!("set -e");
+                // The target machine's own settings are unknown here, so nothing is cleared
+                for change in git::planned_changes(profile, Some(&[])) {
+                    println!("{}", change.shell_command(&["git"], "--global"));
                 }
+                return Ok(());
             }
 
-            // Only set git config if we actually need to change it
-            git::set_git_config(profile, false)?;
+            // Usage history and policy ownership belong to this machine's config
+            let profile = GitProfile { last_used: None, use_count: 0, managed: false, ..profile.clone() };
+            let table = HashMap::from([("profiles", HashMap::from([(name, profile)]))]);
+            print!("{}", toml::to_string(&table)?);
         }
 
-        Commands::Init { profile } => {
-            if config.get_profile(&profile).is_none() {
-                println!("Profile '{}' not found. Available profiles:", profile);
-                for name in config.profiles.keys() {
-                    println!("  {}", name);
-                }
+        Commands::Local { name, adopt, all_worktrees, only, .. } => {
+            if !git::is_git_repo_in_dir(dir) {
+                println!("Not in a git repository");
                 return Ok(());
             }
 
-            dotfile::create_dotfile(".gswitch", &profile)?;
-            println!("Created .gswitch file with profile '{}'", profile);
-        }
-
-        Commands::Import { name } => {
-            match git::get_current_git_config() {
-                Ok(profile) => {
-                    if config.profiles.contains_key(&name) {
-                        println!("Profile '{}' already exists. Use a different name or remove the existing profile first.", name);
+            let Some(name) = name else {
+                let Ok(identity) = git::get_scoped_git_config_in_dir("--local", dir) else {
+                    println!("This repository has no local identity set");
+                    return Ok(());
+                };
+                let Some(matched) = config.find_profile_matching(&identity) else {
+                    println!("No profile matches the local identity {} <{}>", identity.name, identity.email);
+                    println!("Run `gsw import <name>` to save it as a profile");
+                    return Ok(());
+                };
+
+                println!("Local identity matches profile '{}'", matched);
+                if adopt {
+                    git::set_local_config_value_in_dir(resolve::GIT_CONFIG_PROFILE_KEY, &matched, dir)?;
+                    println!("Pinned this repository to '{}' via {}", matched, resolve::GIT_CONFIG_PROFILE_KEY);
+                } else {
+                    println!("Run `gsw local --detect --adopt` to pin this repository to it");
+                }
+                return Ok(());
+            };
+
+            let name = config.canonical_name(&name).to_string();
+            let Some(profile) = config.get_profile(&name) else {
+                println!("Profile '{}' not found", name);
+                return Ok(());
+            };
+
+            let worktrees = if all_worktrees {
+                git::list_worktrees_in_dir(dir)?
+            } else {
+                Vec::new()
+            };
+            if worktrees.len() > 1 {
+                // Per-worktree config needs the extension, otherwise --worktree writes are shared
+                git::set_local_config_value_in_dir("extensions.worktreeConfig", "true", dir)?;
+                for worktree in &worktrees {
+                    git::set_git_config_at_scope_in_dir(profile, "--worktree", Some(worktree), only.as_deref())?;
+                    println!("Switched {} to profile '{}'", worktree.display(), name);
+                }
+                // The worktrees share one origin, so the repo-wide setting covers them all
+                git::apply_sign_hosts_in_dir(profile, dir)?;
+                config.mark_used(&name);
+                config.save()?;
+                after_switch(config, &name);
+                return Ok(());
+            }
+
+            git::set_git_config_in_dir(profile, false, dir, only.as_deref())?;
+            git::apply_sign_hosts_in_dir(profile, dir)?;
+            config.mark_used(&name);
+            config.save()?;
+            println!("Switched to profile '{}' locally", name);
+            after_switch(config, &name);
+        }
+
+        Commands::Status { json } => {
+            let global = git::get_scoped_git_config_in_dir("--global", None::<&Path>).ok();
+            let in_repo = git::is_git_repo();
+            let local = in_repo
+                .then(|| git::get_scoped_git_config_in_dir("--local", None::<&Path>).ok())
+                .flatten();
+            let dotfile_profile = dotfile::find_dotfile_in_dir(None::<&Path>)
+                .and_then(|path| dotfile::read_profile_from_dotfile(path).ok());
+            let resolution = resolve::resolve_profile_in_dir(None, None::<&Path>)?;
+            let resolution = resolve::resolve_from_url_rules(resolution, config, None::<&Path>);
+            let resolved = match resolution {
+                Resolution::Found(name, source) => Some((name, source)),
+                _ => None,
+            };
+            // Nothing resolved means there's nothing to drift from
+            let in_sync = match &resolved {
+                Some((name, _)) => match (config.get_profile(name), git::get_current_git_config()) {
+                    (Some(profile), Ok(effective)) => profile.name == effective.name && profile.email == effective.email,
+                    _ => false,
+                },
+                None => true,
+            };
+
+            if json {
+                let identity = |identity: &Option<GitProfile>| {
+                    identity.as_ref().map(|identity| {
+                        serde_json::json!({
+                            "name": identity.name,
+                            "email": identity.email,
+                            "signing_key": identity.signing_key,
+                        })
+                    })
+                };
+                let status = serde_json::json!({
+                    "global": identity(&global),
+                    "local": identity(&local),
+                    "dotfile_profile": dotfile_profile,
+                    "resolved": resolved.as_ref().map(|(name, _)| name),
+                    "in_sync": in_sync,
+                });
+                println!("{}", serde_json::to_string_pretty(&status)?);
+                return Ok(());
+            }
+
+            let describe = |identity: &Option<GitProfile>| match identity {
+                Some(identity) => format!("{} <{}>", identity.name, identity.email),
+                None => "(none)".to_string(),
+            };
+            println!("Global: {}", describe(&global));
+            if in_repo {
+                println!("Local: {}", describe(&local));
+            } else {
+                println!("Local: (not in a git repository)");
+            }
+            println!(".gswitch: {}", dotfile_profile.as_deref().unwrap_or("(none)"));
+            match &resolved {
+                Some((name, source)) => println!("Resolved: {} (from {})", name, source),
+                None => println!("Resolved: (none)"),
+            }
+            println!("In sync: {}", if in_sync { "yes" } else { "no" });
+        }
+
+        Commands::Current { format, signed_marker, color, identify, scope, from_commit } => {
+            if scope.is_some() {
+                print_identity_by_scope(dir);
+                return Ok(());
+            }
+
+            if let Some(rev) = from_commit {
+                let author = git::commit_author_in_dir(&rev, dir)?;
+                match format.as_str() {
+                    "name" => println!("{}", author.name),
+                    "email" => println!("{}", author.email),
+                    "full" => {
+                        println!("Author of {}:", rev);
+                        println!("  Name: {}", author.name);
+                        println!("  Email: {}", author.email);
+                    }
+                    _ => {
+                        println!("Invalid format: {}. Valid formats: full, name, email", format);
+                        return Ok(());
+                    }
+                }
+                if identify {
+                    match config.find_profile_matching(&author) {
+                        Some(name) => println!("(profile: {})", name),
+                        None => println!("(no matching profile)"),
+                    }
+                }
+                return Ok(());
+            }
+
+            match git::get_current_git_config_in_dir(dir) {
+                Ok(profile) => {
+                    match format.as_str() {
+                        "name" => println!("{}", profile.name),
+                        "email" => println!("{}", profile.email),
+                        "full" => {
+                            let signs = profile.signing_key.is_some()
+                                && git::get_bool_config_in_dir("commit.gpgsign", dir);
+                            if signs && !signed_marker.is_empty() {
+                                if shell::use_color(color) {
+                                    print!("\x1b[32m{}\x1b[0m ", signed_marker);
+                                } else {
+                                    print!("{} ", signed_marker);
+                                }
+                            }
+                            println!("Current git configuration:");
+                            println!("  Name: {}", profile.name);
+                            println!("  Email: {}", profile.email);
+                            if let Some(key) = &profile.signing_key {
+                                println!("  Signing key: {}", key);
+                            }
+                        }
+                        _ => {
+                            println!("Invalid format: {}. Valid formats: full, name, email", format);
+                            return Ok(());
+                        }
+                    }
+                    if identify {
+                        match config.find_profile_matching(&profile) {
+                            Some(name) => println!("(profile: {})", name),
+                            None => println!("(no matching profile)"),
+                        }
+                    }
+                }
+                Err(e) => {
+                    if format.as_str() == "full" {
+                        println!("Failed to get current git configuration: {}", e);
+                    }
+                    // Silent for name/email format when there's an error
+                }
+            }
+        }
+
+        Commands::Auto { log, porcelain, file, export_env, no_boundary, always_local } => {
+            let resolution = resolve_auto(config, file.as_deref(), no_boundary || config.no_boundary, always_local, dir)?;
+            if let Some(ProfileSource::File(path)) = &resolution.source {
+                apply_dotfile_global(config, path)?;
+            }
+            if let AutoOutcome::Switched(name) = &resolution.outcome {
+                write_notify_file(config, name);
+            }
+
+            if let Some(log_path) = log {
+                resolution.append_to_log(&log_path, dir)?;
+            }
+
+            if export_env
+                && let AutoOutcome::Switched(name) | AutoOutcome::Unchanged(name) = &resolution.outcome
+                && let Some(profile) = config.get_profile(name)
+            {
+                for line in profile.export_lines() {
+                    println!("{}", line);
+                }
+            }
+
+            if porcelain {
+                println!("{}", resolution.outcome.porcelain());
+                std::process::exit(resolution.outcome.exit_code());
+            }
+
+            if let (AutoOutcome::MissingProfile(name), Some(source)) = (&resolution.outcome, &resolution.source) {
+                eprintln!("Profile '{}' specified in {} not found", name, source);
+                // A file named explicitly (e.g. by CI) is expected to work, so say it didn't
+                if file.is_some() {
+                    std::process::exit(resolution.outcome.exit_code());
+                }
+            }
+        }
+
+        Commands::Init { profile, file, at, no_newline } => {
+            let inferred = profile.or_else(|| {
+                git::get_current_git_config_in_dir(dir)
+                    .ok()
+                    .and_then(|identity| config.find_profile_matching(&identity))
+                    .or_else(|| config.current_profile.clone())
+            });
+            let Some(profile) = inferred else {
+                println!("Could not infer a profile from the current git identity. Specify one: gsw init <profile>");
+                return Ok(());
+            };
+            let profile = config.canonical_name(&profile).to_string();
+
+            if config.get_profile(&profile).is_none() {
+                println!("Profile '{}' not found. Available profiles:", profile);
+                for name in config.profiles.keys() {
+                    println!("  {}", name);
+                }
+                return Ok(());
+            }
+
+            if let Some(at) = &at {
+                if at.is_absolute() || at.components().any(|part| part == std::path::Component::ParentDir) {
+                    println!("--at must be a relative path without '..'");
+                    return Ok(());
+                }
+                if config.dotfile_subpath.as_deref().map(Path::new) != Some(at.as_path())
+                    && at.file_name().is_none_or(|name| name != ".gswitch")
+                {
+                    warnings::warn(format!(
+                        "{} won't be found unless dotfile_subpath is set to it in config.toml",
+                        at.display()
+                    ));
+                }
+            }
+            // --at and the default .gswitch are relative to the repo, --file to where gsw runs
+            let in_dir = |path: PathBuf| match dir {
+                Some(dir) => dir.join(path),
+                None => path,
+            };
+            let at = at.map(in_dir);
+            if let Some(parent) = at.as_deref().and_then(Path::parent).filter(|parent| !parent.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            let file = file.or(at);
+
+            // When overwriting, keep the existing file's newline convention
+            let path = file.clone().unwrap_or_else(|| in_dir(PathBuf::from(".gswitch")));
+            let newline = !no_newline && dotfile::has_trailing_newline(&path).unwrap_or(true);
+            if newline {
+                dotfile::create_dotfile(&path, &profile)?;
+            } else {
+                dotfile::write_dotfile(&path, &profile, false)?;
+            }
+            match file {
+                Some(path) => println!("Created {} with profile '{}'", path.display(), profile),
+                None => println!("Created .gswitch file with profile '{}'", profile),
+            }
+        }
+
+        Commands::Import { name, update, detect_key, scope } => {
+            if let Some(dir) = dir
+                && !git::is_git_repo_in_dir(Some(dir))
+            {
+                println!("{} is not a git repository", dir.display());
+                return Ok(());
+            }
+            let identity = match scope.as_str() {
+                "effective" => git::get_current_git_config_in_dir(dir),
+                scope => git::get_scoped_git_config_in_dir(&format!("--{}", scope), dir),
+            };
+            match identity {
+                Ok(mut profile) => {
+                    if detect_key && profile.signing_key.is_none() {
+                        profile.signing_key = keys::detect_signing_key()
+                            .map(|key| normalize_signing_key(&key, None, false))
+                            .transpose()?;
+                    }
+                    if update && config.is_managed(&name) {
+                        println!("Profile '{}' is managed by policy and can't be edited", name);
+                        return Ok(());
+                    }
+                    if update && let Some(existing) = config.profiles.get_mut(&name) {
+                        let mut changes = Vec::new();
+                        if existing.name != profile.name {
+                            changes.push(format!("  Name: {} -> {}", existing.name, profile.name));
+                            existing.name = profile.name;
+                        }
+                        if existing.email != profile.email {
+                            changes.push(format!("  Email: {} -> {}", existing.email, profile.email));
+                            existing.email = profile.email;
+                        }
+                        // A live identity without a key leaves the stored key alone
+                        if let Some(key) = profile.signing_key
+                            && existing.signing_key.as_ref() != Some(&key)
+                        {
+                            let old = existing.signing_key.as_deref().unwrap_or("(none)");
+                            changes.push(format!("  Signing key: {} -> {}", old, key));
+                            existing.signing_key = Some(key);
+                        }
+
+                        if changes.is_empty() {
+                            println!("Profile '{}' already matches the current git identity", name);
+                            return Ok(());
+                        }
+                        config.save()?;
+                        println!("Updated profile '{}' from the current git identity:", name);
+                        for change in changes {
+                            println!("{}", change);
+                        }
+                        return Ok(());
+                    }
+
+                    if config.profiles.contains_key(&name) {
+                        println!("Profile '{}' already exists. Use a different name, --update, or remove the existing profile first.", name);
                         return Ok(());
                     }
 
@@ -241,10 +1412,572 @@ fn main() -> Result<()> {
             }
         }
 
-        Commands::Activate { shell } => {
-            let script = match shell.as_str() {
-                "bash" | "zsh" => {
-                    r#"_gsw_auto_switch() {
+        Commands::PinKey { key, origin } => {
+            if !git::is_git_repo() {
+                println!("Not in a git repository");
+                return Ok(());
+            }
+            if !keys::signing_key_available(&key, None) {
+                println!("Signing key '{}' not found in the keyring or on disk", key);
+                return Ok(());
+            }
+            git::set_local_config_value_in_dir("user.signingkey", &key, None::<&Path>)?;
+            if let Some(origin) = &origin {
+                git::set_local_config_value_in_dir("gswitch.keyOrigin", origin, None::<&Path>)?;
+            }
+            println!("Pinned signing key {} to this repository", key);
+        }
+
+        Commands::RemoteApply { target, profile: name } => {
+            let repo = remote::RemoteRepo::parse(&target)?;
+            let Some(profile) = config.get_profile(&name) else {
+                println!("Profile '{}' not found", name);
+                return Ok(());
+            };
+            profile.check_complete()?;
+            let status = std::process::Command::new("ssh")
+                .args(repo.ssh_args(profile))
+                .status()
+                .context("Failed to run ssh")?;
+            if !status.success() {
+                anyhow::bail!(
+                    "Applying '{}' to {} over ssh failed ({})",
+                    name,
+                    target,
+                    status
+                );
+            }
+            println!("Switched {} to profile '{}'", target, name);
+        }
+
+        Commands::DiffLive { name, scope } => {
+            let Some(profile) = config.get_profile(&name) else {
+                println!("Profile '{}' not found", name);
+                return Ok(());
+            };
+            let scope = scope.map(|scope| format!("--{}", scope));
+            let diffs = git::profile_diff_in_dir(profile, scope.as_deref(), None::<&Path>);
+            if diffs.is_empty() {
+                println!("Profile '{}' matches the live git config", name);
+                return Ok(());
+            }
+            println!("Profile '{}' differs from the live git config:", name);
+            for diff in diffs {
+                println!(
+                    "  {}: {} (stored) != {} (live)",
+                    diff.key,
+                    diff.stored.as_deref().unwrap_or("(unset)"),
+                    diff.live.as_deref().unwrap_or("(unset)")
+                );
+            }
+            std::process::exit(1);
+        }
+
+        Commands::Hooks { action } => {
+            if !git::is_git_repo() {
+                println!("Not in a git repository");
+                return Ok(());
+            }
+            let hooks_dir = git::hooks_dir_in_dir(None::<&Path>)?;
+            for name in hooks::HOOK_NAMES {
+                let hook = hooks_dir.join(name);
+                let changed = if action == "install" {
+                    hooks::install(&hook)?
+                } else {
+                    hooks::uninstall(&hook)?
+                };
+                match (action.as_str(), changed) {
+                    ("install", true) => println!("Installed {} hook: {}", name, hook.display()),
+                    ("install", false) => println!("{} hook already installed", name),
+                    (_, true) => println!("Removed {} hook from {}", name, hook.display()),
+                    (_, false) => println!("{} hook not installed", name),
+                }
+            }
+        }
+
+        Commands::ImportSshConfig { ssh_config, yes } => {
+            let path = match ssh_config {
+                Some(path) => path,
+                None => dirs::home_dir()
+                    .context("Could not determine home directory")?
+                    .join(".ssh")
+                    .join("config"),
+            };
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let hosts = sshconfig::parse_ssh_config(&content);
+            if hosts.is_empty() {
+                println!("No host aliases with an IdentityFile found in {}", path.display());
+                return Ok(());
+            }
+
+            println!("Host aliases in {}:", path.display());
+            let mut created = Vec::new();
+            for host in hosts {
+                let ssh_command = format!("ssh -i {} -o IdentitiesOnly=yes", shell::quote(&host.identity_file));
+                if config.profiles.contains_key(&host.alias) {
+                    println!("  {}: profile already exists, skipping", host.alias);
+                    continue;
+                }
+                println!("  {}: {}", host.alias, ssh_command);
+                if yes {
+                    let profile = GitProfile {
+                        ssh_command: Some(ssh_command.clone()),
+                        ..Default::default()
+                    };
+                    config.add_profile(host.alias.clone(), profile);
+                }
+                created.push((host.alias, ssh_command));
+            }
+            if created.is_empty() {
+                return Ok(());
+            }
+
+            if yes {
+                config.save()?;
+                println!("Created {} skeleton profile(s). Complete each with its name and email:", created.len());
+            } else {
+                println!("Run with --yes to create these profiles, then complete each with its name and email:");
+            }
+            for (name, ssh_command) in created {
+                println!(
+                    "  gsw add {} --user-name <name> --email <email> --ssh-command {}",
+                    name,
+                    shell::quote(&ssh_command)
+                );
+            }
+        }
+
+        Commands::Profile { action: ProfileCommands::Dedupe { dry_run, keep, root } } => {
+            let groups = config.duplicate_identity_groups();
+            if groups.is_empty() {
+                println!("No duplicate profiles found");
+                return Ok(());
+            }
+
+            let dotfiles = root
+                .or_else(|| git::find_repo_root_in_dir(None::<&Path>))
+                .map(dotfile::find_dotfiles_under)
+                .unwrap_or_default();
+
+            for group in &groups {
+                let keeper = dedupe_keeper(group, keep.as_deref());
+                let duplicates: Vec<&str> = group
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|name| *name != keeper)
+                    .collect();
+                let names_duplicate = |named: &str| duplicates.contains(&named);
+
+                if dry_run {
+                    println!("Would keep '{}', removing: {}", keeper, duplicates.join(", "));
+                    for path in &dotfiles {
+                        if std::fs::read_to_string(path)
+                            .is_ok_and(|content| dotfile::named_profiles(&content).into_iter().any(names_duplicate))
+                        {
+                            println!("Would update {} to use '{}'", path.display(), keeper);
+                        }
+                    }
+                    continue;
+                }
+
+                for duplicate in &duplicates {
+                    config.merge_profile_into(duplicate, keeper);
+                }
+                for path in &dotfiles {
+                    if dotfile::replace_profile_lines(path, names_duplicate, keeper)? {
+                        println!("Updated {} to use '{}'", path.display(), keeper);
+                    }
+                }
+                println!("Kept '{}', removed: {}", keeper, duplicates.join(", "));
+            }
+
+            if !dry_run {
+                config.save()?;
+            }
+        }
+
+        Commands::Reorder { name, position } => {
+            let Some(profile) = config.profiles.get_mut(&name) else {
+                println!("Profile '{}' not found", name);
+                return Ok(());
+            };
+            if profile.managed {
+                println!("Profile '{}' is managed by policy and can't be edited", name);
+                return Ok(());
+            }
+            profile.order = Some(position);
+            config.save()?;
+            println!("Profile '{}' moved to position {}", name, position);
+        }
+
+        Commands::Rename { old, new } => {
+            config.rename_profile(&old, &new)?;
+            config.save()?;
+            println!("Profile '{}' renamed to '{}'", old, new);
+            if let Some(root) = git::find_repo_root_in_dir(None::<&Path>)
+                && !dotfile::find_dotfiles_naming(&root, |named| named == old).is_empty()
+            {
+                warnings::warn(format!(".gswitch files in this repository still name '{}'", old));
+            }
+        }
+
+        Commands::Profile { action: ProfileCommands::SwapNameEmail { name, yes } } => {
+            let Some(profile) = config.profiles.get_mut(&name) else {
+                println!("Profile '{}' not found", name);
+                return Ok(());
+            };
+            if profile.managed {
+                println!("Profile '{}' is managed by policy and can't be edited", name);
+                return Ok(());
+            }
+            println!("Name: {} -> {}", profile.name, profile.email);
+            println!("Email: {} -> {}", profile.email, profile.name);
+            if !yes {
+                print!("Swap them? [y/N] ");
+                std::io::stdout().flush()?;
+                let mut answer = String::new();
+                std::io::stdin().read_line(&mut answer)?;
+                if !answer.trim().eq_ignore_ascii_case("y") {
+                    println!("Profile '{}' unchanged", name);
+                    return Ok(());
+                }
+            }
+            std::mem::swap(&mut profile.name, &mut profile.email);
+            if let Err(err) = validate_email(&profile.email) {
+                warnings::warn(format!("{}; check profile '{}' by hand", err, name));
+            }
+            config.save()?;
+            println!("Profile '{}' now uses {} <{}>", name, config.profiles[&name].name, config.profiles[&name].email);
+        }
+
+        Commands::SetEmail { name, email } => {
+            validate_email(&email)?;
+            let Some(profile) = config.profiles.get_mut(&name) else {
+                println!("Profile '{}' not found", name);
+                return Ok(());
+            };
+            if profile.managed {
+                println!("Profile '{}' is managed by policy and can't be edited", name);
+                return Ok(());
+            }
+            let old = std::mem::replace(&mut profile.email, email.clone());
+            config.save()?;
+            println!("Profile '{}' email: {} -> {}", name, old, email);
+        }
+
+        Commands::SetSigning { name, format, key, allowed_signers_file } => {
+            if allowed_signers_file.is_some() && format != "ssh" {
+                anyhow::bail!("--allowed-signers-file only applies to ssh signing");
+            }
+            let key = normalize_signing_key(&key, Some(&format), false)?;
+            keys::check_key_matches_format(&key, &format)?;
+            let Some(profile) = config.profiles.get_mut(&name) else {
+                println!("Profile '{}' not found", name);
+                return Ok(());
+            };
+            if profile.managed {
+                println!("Profile '{}' is managed by policy and can't be edited", name);
+                return Ok(());
+            }
+            profile.signing_key = Some(key.clone());
+            profile.signing_format = Some(format.clone());
+            // A file for verifying ssh signatures means nothing to another format
+            if format != "ssh" || allowed_signers_file.is_some() {
+                profile.allowed_signers_file = allowed_signers_file;
+            }
+            config.save()?;
+            println!("Profile '{}' now signs with {} key {}", name, format, key);
+        }
+
+        Commands::Refresh => {
+            let Some(name) = config.current_profile.clone() else {
+                println!("No current profile set");
+                return Ok(());
+            };
+            let Some(profile) = config.get_profile(&name) else {
+                println!("Current profile '{}' not found", name);
+                return Ok(());
+            };
+            git::set_git_config(profile, true)?;
+            println!("Re-applied profile '{}' globally", name);
+        }
+
+        Commands::ValidateAll { json, check_keys } => {
+            let problems = config.validate(check_keys);
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&problems)?);
+            } else if problems.is_empty() {
+                println!("No problems found");
+            } else {
+                for problem in &problems {
+                    println!("  {}: {} {}", problem.profile, problem.field, problem.problem);
+                }
+                println!("{} problem(s) found", problems.len());
+            }
+
+            if !problems.is_empty() {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::SetupGlobalInclude => {
+            let path = Config::global_include_path()?;
+            if !path.exists() {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent).context("Failed to create config directory")?;
+                }
+                std::fs::write(&path, "# Managed by gswitch: global switches write here\n")
+                    .with_context(|| format!("Failed to create {}", path.display()))?;
+            }
+            if git::add_global_include(&path)? {
+                println!("Added include.path = {} to the global git config", path.display());
+            } else {
+                println!("The global git config already includes {}", path.display());
+            }
+            config.global_include = true;
+            config.save()?;
+            println!("Global switches now write to {}", path.display());
+            println!("user.* settings later in the global git config still win over the include, so remove them there");
+        }
+
+        Commands::Reset { scope } => {
+            let global = scope.as_deref() == Some("global");
+            if !global && !git::is_git_repo() {
+                println!("Not in a git repository");
+                return Ok(());
+            }
+
+            git::reset_git_config_in_dir(global, None::<&Path>)?;
+            if global {
+                config.current_profile = None;
+                config.save()?;
+                println!("Cleared gswitch-managed settings from global git config");
+            } else {
+                println!("Cleared gswitch-managed settings from this repository");
+            }
+        }
+
+        Commands::ProfilesSort => {
+            config.sort_lists();
+            config.save()?;
+            println!("Sorted {} profile(s) in {}", config.profiles.len(), Config::config_path()?.display());
+        }
+
+        Commands::CleanConfig { dry_run } => {
+            let changes = config.clean();
+            if changes.is_empty() {
+                println!("Config is already clean");
+                return Ok(());
+            }
+            for change in &changes {
+                println!("{}", change);
+            }
+            if dry_run {
+                println!("Dry run: config.toml was not changed");
+            } else {
+                config.save()?;
+            }
+        }
+
+        Commands::ConfigEdit => {
+            let path = Config::config_path()?;
+            // Under --no-save the edit goes to a throwaway copy, checked the same way
+            let path = if config.no_save {
+                let content = match std::fs::read_to_string(&path) {
+                    Ok(content) => content,
+                    Err(_) => toml::to_string_pretty(config).context("Failed to serialize config")?,
+                };
+                let copy = std::env::temp_dir().join(format!("gswitch-config-edit-{}.toml", std::process::id()));
+                std::fs::write(&copy, content).context("Failed to write a copy of the config file")?;
+                copy
+            } else {
+                if !path.exists() {
+                    config.save()?;
+                }
+                path
+            };
+            let backup = std::fs::read_to_string(&path).context("Failed to read config file")?;
+            let editor = std::env::var("VISUAL")
+                .or_else(|_| std::env::var("EDITOR"))
+                .unwrap_or_else(|_| "vi".to_string());
+
+            loop {
+                // Run through the shell so EDITOR values with arguments (`code -w`) work
+                let status = std::process::Command::new("sh")
+                    .args(["-c", &format!("{} \"$1\"", editor), "sh"])
+                    .arg(&path)
+                    .status()
+                    .with_context(|| format!("Failed to run editor '{}'", editor))?;
+                if !status.success() {
+                    println!("Editor exited with {}", status);
+                }
+
+                let Err(err) = Config::load_from(&path) else {
+                    if config.no_save {
+                        println!("Config is valid, but not saved (--no-save)");
+                    } else {
+                        println!("Config saved");
+                    }
+                    break;
+                };
+                println!("Config is invalid: {:#}", err);
+                print!("Reopen the editor? [Y/n] (no restores the previous config) ");
+                std::io::stdout().flush()?;
+                let mut answer = String::new();
+                let read = std::io::stdin().read_line(&mut answer)?;
+                if read == 0 || answer.trim().eq_ignore_ascii_case("n") {
+                    std::fs::write(&path, &backup).context("Failed to restore config file")?;
+                    println!();
+                    println!("Restored the previous config");
+                    break;
+                }
+            }
+            if config.no_save {
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+
+        Commands::Policy { action: PolicyCommands::Sync { url } } => {
+            if let Some(url) = url {
+                config.policy_url = Some(url);
+            }
+            let Some(url) = config.policy_url.clone() else {
+                println!("No policy_url configured; pass --url to set one");
+                return Ok(());
+            };
+
+            match policy::sync(config, &url) {
+                Ok((count, source)) => {
+                    config.save()?;
+                    match source {
+                        policy::SyncSource::Network => {
+                            println!("Synced {} managed profile(s) from {}", count, url);
+                        }
+                        policy::SyncSource::Cache(reason) => {
+                            warnings::warn(format!("Could not reach policy server ({})", reason));
+                            println!("Using {} managed profile(s) from the cached bundle", count);
+                        }
+                    }
+                }
+                Err(err) => {
+                    config.save()?;
+                    println!("Policy sync failed: {:#}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Reconcile { root, apply } => {
+            let repos = git::find_repos_under(&root);
+            let mut drifted = 0;
+            for repo in &repos {
+                match reconcile_repo(config, repo, apply)? {
+                    ReconcileOutcome::NoProfile => {}
+                    ReconcileOutcome::MissingProfile(name) => {
+                        println!("  missing profile '{}': {}", name, repo.display());
+                    }
+                    ReconcileOutcome::InSync(name) => {
+                        println!("  in sync ({}): {}", name, repo.display());
+                    }
+                    ReconcileOutcome::Drifted(name) => {
+                        drifted += 1;
+                        let action = if apply { "fixed" } else { "drifted" };
+                        println!("  {} ({}): {}", action, name, repo.display());
+                    }
+                }
+            }
+
+            let action = if apply { "fixed" } else { "drifted" };
+            println!("{} repo(s) checked, {} {}", repos.len(), drifted, action);
+            if drifted > 0 && !apply {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::GenerateIncludeif { root, output } => {
+            let root = root.canonicalize().context("Failed to resolve root directory")?;
+            let mut rules = Vec::new();
+            for path in dotfile::find_dotfiles_under(&root) {
+                let Ok(profile) = dotfile::read_profile_from_dotfile(&path) else {
+                    continue;
+                };
+                // Aliases share the profile's include file
+                let profile = config.canonical_name(&profile).to_string();
+                if config.get_profile(&profile).is_none() {
+                    eprintln!("Skipping {}: profile '{}' not found", path.display(), profile);
+                    continue;
+                }
+                if let Some(directory) = path.parent() {
+                    rules.push(includeif::DirectoryRule { directory: directory.to_path_buf(), profile });
+                }
+            }
+
+            if rules.is_empty() {
+                println!("No .gswitch files found under {}", root.display());
+                return Ok(());
+            }
+
+            let mut names: Vec<&str> = rules.iter().map(|rule| rule.profile.as_str()).collect();
+            names.sort();
+            names.dedup();
+            let profiles: Vec<(&str, &GitProfile)> = names
+                .into_iter()
+                .filter_map(|name| config.get_profile(name).map(|profile| (name, profile)))
+                .collect();
+
+            let include_dir = match &output {
+                Some(dir) => {
+                    std::fs::create_dir_all(dir).context("Failed to create output directory")?;
+                    dir.canonicalize().context("Failed to resolve output directory")?
+                }
+                None => Config::config_path()?
+                    .parent()
+                    .map(|dir| dir.join("includes"))
+                    .unwrap_or_else(|| PathBuf::from("includes")),
+            };
+            let blocks = includeif::render_includeif_blocks(&rules, &include_dir);
+
+            if output.is_some() {
+                let snippet_path = include_dir.join("includeif.gitconfig");
+                std::fs::write(&snippet_path, &blocks).context("Failed to write includeIf snippet")?;
+                for (name, profile) in profiles {
+                    let path = includeif::include_file_path(&include_dir, name);
+                    std::fs::write(&path, includeif::render_profile_include(profile))
+                        .context("Failed to write profile include file")?;
+                    println!("Wrote {}", path.display());
+                }
+                println!("Wrote {}", snippet_path.display());
+                println!("Add it to ~/.gitconfig with:\n[include]\n\tpath = {}", snippet_path.display());
+            } else {
+                print!("{}", blocks);
+                for (name, profile) in profiles {
+                    println!();
+                    println!("# {}", includeif::include_file_path(&include_dir, name).display());
+                    print!("{}", includeif::render_profile_include(profile));
+                }
+            }
+        }
+
+        Commands::Activate { .. }
+        | Commands::ShellInit
+        | Commands::Version { .. }
+        | Commands::Completions { .. }
+        | Commands::Prompt { .. }
+        | Commands::Doctor { .. } => {
+            unreachable!("commands that don't need the config run before it's loaded")
+        }
+    }
+
+    Ok(())
+}
+
+/// The `gsw activate` integration script for a shell, if it's supported
+fn activation_script(shell: &str) -> Option<&'static str> {
+    let script = match shell {
+        "bash" | "zsh" => {
+            r#"_gsw_auto_switch() {
     if command -v gsw >/dev/null 2>&1; then
         gsw auto 2>/dev/null
     fi
@@ -252,6 +1985,7 @@ fn main() -> Result<()> {
 
 case "$-" in
     *i*) 
+        export GSWITCH_HOOK=1
         if [[ "${shell}" == "zsh" ]]; then
             autoload -U add-zsh-hook
             add-zsh-hook chpwd _gsw_auto_switch
@@ -264,17 +1998,18 @@ case "$-" in
         _gsw_auto_switch
         ;;
 esac"#
-                }
-                "fish" => {
-                    r#"function _gsw_auto_switch --on-variable PWD
+        }
+        "fish" => {
+            r#"function _gsw_auto_switch --on-variable PWD
     if command -v gsw >/dev/null 2>&1
         gsw auto 2>/dev/null
     end
 end
+set -gx GSWITCH_HOOK 1
 _gsw_auto_switch"#
-                }
-                "nushell" => {
-                    r#"def _gsw_auto_switch [] {
+        }
+        "nushell" => {
+            r#"def _gsw_auto_switch [] {
     if (which gsw | is-not-empty) {
         try { gsw auto } | ignore
     }
@@ -286,36 +2021,614 @@ $env.config = ($env.config | upsert hooks {
     }
 })
 
+$env.GSWITCH_HOOK = "1"
 _gsw_auto_switch"#
-                }
-                _ => {
-                    println!("Unsupported shell: {}. Supported shells: bash, zsh, fish, nushell", shell);
-                    return Ok(());
-                }
+        }
+        _ => return None,
+    };
+    Some(script)
+}
+
+/// Print whether `gsw activate` is loaded in the current shell, and if not, whether
+/// the shell's rc file loads it (so a new shell would pick it up)
+fn check_activation(shell: &str) {
+    if std::env::var(shell::HOOK_ENV).is_ok_and(|value| value == "1") {
+        println!("Shell integration is active");
+        return;
+    }
+    let Some(rc_file) = shell::rc_file(shell) else {
+        println!("Unsupported shell: {}. Supported shells: bash, zsh, fish, nushell", shell);
+        return;
+    };
+    let installed = std::fs::read_to_string(&rc_file).is_ok_and(|content| ["gsw activate", "gsw shell-init"].iter().any(|line| content.contains(line)));
+    if installed {
+        println!("Shell integration is not active, but {} loads it: start a new shell", rc_file.display());
+    } else {
+        println!("Shell integration is not active: add gsw activate {} to {} (see the README)", shell, rc_file.display());
+    }
+}
+
+/// Commands that don't need the config, so they work even when it can't be found or parsed.
+///
+/// `no_save` and `dir` are the global `--no-save` and `--dir`, for commands that
+/// load the config themselves or act on a repo.
+fn run_without_config(command: Commands, no_save: bool, dir: Option<&Path>) -> Result<()> {
+    match command {
+        Commands::Doctor { fix } => {
+            // Loaded here rather than up front, so a config that doesn't parse is
+            // reported like any other problem instead of aborting the checks
+            let (mut config, unreadable) = match Config::load_lenient() {
+                Ok((config, errors)) => (config, errors.into_iter().map(|error| format!("invalid profile {}", error)).collect()),
+                Err(err) => (Config::default(), vec![format!("{}: {:#}", Config::config_path()?.display(), err)]),
             };
-            
-            println!("{}", script);
+            // Saving would drop whatever couldn't be read
+            config.no_save = no_save || !unreadable.is_empty();
+            doctor(&mut config, fix, unreadable)?;
         }
 
-        Commands::Prompt => {
-            // Fast path: only check current directory for .gswitch file
-            // Use absolute path to ensure we're checking exactly the current directory
-            let current_dir = std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("."));
-            let gswitch_path = current_dir.join(".gswitch");
-            
-            if gswitch_path.exists()
-                && let Ok(content) = std::fs::read_to_string(&gswitch_path) {
-                    let profile_name = content.trim();
-                    if !profile_name.is_empty() && !profile_name.chars().all(|c| c.is_whitespace()) {
-                        print!(" {}", profile_name);
-                        std::process::exit(0);
-                    }
+        Commands::Activate { shell, check } => {
+            if check {
+                check_activation(&shell);
+                return Ok(());
+            }
+            match activation_script(&shell) {
+                Some(script) => println!("{}", script),
+                None => println!("Unsupported shell: {}. Supported shells: bash, zsh, fish, nushell", shell),
+            }
+        }
+
+        Commands::Version { verbose } => {
+            println!("gsw {}", env!("CARGO_PKG_VERSION"));
+            if !verbose {
+                return Ok(());
+            }
+            println!("git: {}", git::version().unwrap_or_else(|| "not found".to_string()));
+            let features: Vec<&str> = [("policy", cfg!(feature = "policy"))]
+                .into_iter()
+                .filter_map(|(feature, enabled)| enabled.then_some(feature))
+                .collect();
+            println!("features: {}", if features.is_empty() { "none".to_string() } else { features.join(", ") });
+            let show = |path: Option<PathBuf>| path.map_or_else(|| "unknown".to_string(), |path| path.display().to_string());
+            println!("config: {}", show(Config::config_path().ok()));
+            println!("state: {}", show(cache::cache_path()));
+        }
+
+        Commands::ShellInit => {
+            let Some(shell) = shell::detect_activation_shell() else {
+                anyhow::bail!(
+                    "Could not detect a supported shell from $SHELL. Use gsw activate <shell> with one of: bash, zsh, fish, nushell"
+                );
+            };
+            println!("{}", activation_script(shell).unwrap_or_default());
+        }
+
+        Commands::Completions { shell: target, install, force } => {
+            let Some(target) = target.or_else(shell::detect_shell) else {
+                println!("Could not detect your shell from $SHELL. Specify one: gsw completions <shell>");
+                return Ok(());
+            };
+
+            let mut script = Vec::new();
+            clap_complete::generate(target, &mut Cli::command(), "gsw", &mut script);
+
+            if !install {
+                std::io::stdout().write_all(&script)?;
+                return Ok(());
+            }
+
+            let Some(path) = shell::completion_install_path(target) else {
+                println!("Installing completions for {} is not supported. Print them with: gsw completions {}", target, target);
+                return Ok(());
+            };
+
+            if path.exists() && !force {
+                let existing = std::fs::read_to_string(&path).unwrap_or_default();
+                if !existing.starts_with(shell::COMPLETION_MARKER) {
+                    println!("{} already exists and wasn't written by gsw. Use --force to overwrite it.", path.display());
+                    return Ok(());
                 }
-            // Exit with error code if no valid profile found
-            // This tells Starship not to display anything
-            std::process::exit(1);
+            }
+
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).context("Failed to create completions directory")?;
+            }
+            let mut content = format!("{}\n", shell::COMPLETION_MARKER).into_bytes();
+            content.extend_from_slice(&script);
+            std::fs::write(&path, content).context("Failed to write completions")?;
+            println!("Installed {} completions to {}", target, path.display());
+            if target == clap_complete::Shell::Zsh {
+                println!("Make sure {} is in your fpath before compinit runs", path.parent().unwrap_or(&path).display());
+            }
         }
+        // Prompt runs on every shell prompt, so it only loads the config when it has to
+        Commands::Prompt { file, global_fallback, kv, distinguish, timeout, walk, no_boundary } => {
+            let walk = walk.then_some(PromptWalk { no_boundary });
+            prompt(file, dir, walk, global_fallback, kv, distinguish, timeout.map(std::time::Duration::from_millis))
+        }
+
+        _ => unreachable!("only commands that don't need the config run without it"),
     }
 
     Ok(())
 }
+
+/// How `gsw prompt --walk` searches for the profile file
+struct PromptWalk {
+    /// Continue above the repo root, up to $HOME
+    no_boundary: bool,
+}
+
+/// Print the profile for a shell prompt and exit: 0 with ` <profile>` (no trailing
+/// newline) or, with `kv`, `profile=<profile>\n`; 1 with nothing.
+/// The exit code tells Starship whether to display anything.
+/// With `timeout`, the file is read on a worker thread so a hung filesystem (e.g. a
+/// network mount) can't freeze the shell: past the deadline it prints nothing and exits 1.
+fn prompt(
+    file: Option<PathBuf>,
+    dir: Option<&Path>,
+    walk: Option<PromptWalk>,
+    global_fallback: bool,
+    kv: bool,
+    distinguish: bool,
+    timeout: Option<std::time::Duration>,
+) -> ! {
+    let start = dir
+        .map(Path::to_path_buf)
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_else(|| std::path::PathBuf::from("."));
+    let lookup = {
+        let start = start.clone();
+        move || match (file, walk) {
+            (Some(file), _) => read_prompt_dotfile(&file),
+            (None, Some(walk)) => walk_prompt_dotfile(&start, &walk),
+            // Fast path: only check the current directory for a .gswitch file
+            (None, None) => read_prompt_dotfile(&start.join(".gswitch")),
+        }
+    };
+
+    let mut profile_name = match timeout {
+        None => lookup(),
+        Some(timeout) => {
+            let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+            std::thread::spawn(move || {
+                let _ = sender.send(lookup());
+            });
+            // Exiting also ends the worker if it's still stuck in the read
+            receiver.recv_timeout(timeout).unwrap_or_else(|_| std::process::exit(1))
+        }
+    };
+
+    if profile_name.is_none() && global_fallback {
+        profile_name = Config::load().ok().and_then(|config| config.current_profile);
+    }
+
+    match profile_name {
+        Some(name) if kv => println!("profile={}", name),
+        Some(name) => print!(" {}", name),
+        // Only pay for the repo check when the caller asked to tell the cases apart
+        None if distinguish && git::find_repo_root_in_dir(Some(&start)).is_none() => std::process::exit(2),
+        None => std::process::exit(1),
+    }
+    std::process::exit(0);
+}
+
+/// The profile `gsw prompt --walk` shows: from the nearest dotfile up to the repo
+/// root, then as `auto` would with `no_boundary` and `home_fallback`. This is the one
+/// prompt mode that reads config.toml, for those settings and `dotfile_subpath`.
+fn walk_prompt_dotfile(start: &Path, walk: &PromptWalk) -> Option<String> {
+    let config = Config::load().unwrap_or_default();
+    if let Some(subpath) = &config.dotfile_subpath {
+        dotfile::set_subpath(subpath);
+    }
+    let home = dirs::home_dir();
+    let path = dotfile::find_dotfile_in_dir(Some(start))
+        .or_else(|| {
+            let home = home.as_deref().filter(|_| walk.no_boundary || config.no_boundary)?;
+            dotfile::find_dotfile_above_root(Some(start), home)
+        })
+        .or_else(|| {
+            let home = home.as_deref().filter(|_| config.home_fallback)?;
+            git::find_repo_root_in_dir(Some(start))?;
+            dotfile::dotfile_in(home)
+        })?;
+    read_prompt_dotfile(&path)
+}
+
+/// The repo's own profile named by the .gswitch file at `path`, if it exists and
+/// names one; `global`, `host:` and `only-dotfile` lines are read as in `auto`
+fn read_prompt_dotfile(path: &Path) -> Option<String> {
+    if !path.is_file() {
+        return None;
+    }
+    dotfile::read_dotfile_spec(path).ok()?.local
+}
+
+/// Print `user.*` identity keys per scope as an aligned table, `-` where unset
+fn print_identity_by_scope(dir: Option<&Path>) {
+    let scopes = [Some("--system"), Some("--global"), Some("--local"), None];
+    let header = ["KEY", "SYSTEM", "GLOBAL", "LOCAL", "EFFECTIVE"];
+    let mut rows: Vec<Vec<String>> = vec![header.iter().map(|title| title.to_string()).collect()];
+    for key in ["user.name", "user.email", "user.signingkey"] {
+        let mut row = vec![key.to_string()];
+        for scope in scopes {
+            let value = git::get_scoped_config_value_in_dir(scope, key, dir);
+            row.push(value.unwrap_or_else(|| "-".to_string()));
+        }
+        rows.push(row);
+    }
+
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0))
+        .collect();
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+}
+
+/// Print the identity git will use here once `profile` is applied, then wait for
+/// Enter unless `yes`. Fields left out by `only` keep their current value, and a
+/// repository's own settings still win over a global switch. False if stdin closes.
+fn confirm_resulting_identity(profile: &GitProfile, local: bool, only: Option<&[String]>, yes: bool) -> Result<bool> {
+    let scope = if local { "--local" } else { "--global" };
+    let resulting = |field: &str, key: &str, value: Option<String>| {
+        let applied = only.is_none_or(|fields| fields.iter().any(|f| f == field));
+        let current = || git::get_scoped_config_value_in_dir(Some(scope), key, None::<&Path>);
+        let value = if applied { value.or_else(current) } else { current() };
+        if local {
+            return value;
+        }
+        git::get_scoped_config_value_in_dir(Some("--local"), key, None::<&Path>).or(value)
+    };
+
+    let name = resulting("name", "user.name", Some(profile.name.clone()));
+    let email = resulting("email", "user.email", Some(profile.email.clone()));
+    let signing_key = resulting("signing_key", "user.signingkey", profile.signing_key.clone());
+
+    println!("After switching, git will commit as:");
+    println!(
+        "  {} <{}>",
+        name.as_deref().unwrap_or("(no name)"),
+        email.as_deref().unwrap_or("(no email)")
+    );
+    match signing_key {
+        Some(key) => println!("  Signing key: {}", key),
+        None => println!("  Signing: no key"),
+    }
+    if yes {
+        return Ok(true);
+    }
+
+    print!("Press Enter to continue, Ctrl-C to cancel ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    let read = std::io::stdin().read_line(&mut answer)?;
+    if read == 0 {
+        println!();
+    }
+    Ok(read > 0)
+}
+
+/// Everything that follows `switch`, `use` or `local` applying a profile
+fn after_switch(config: &Config, name: &str) {
+    write_notify_file(config, name);
+    run_switch_hook(config, name);
+}
+
+/// Write the newly active profile to the configured `notify_file` for watchers
+/// such as status bars. It's replaced in one rename so readers never see it half-written.
+fn write_notify_file(config: &Config, name: &str) {
+    let Some(path) = config.notify_file.as_deref().map(keys::expand_home) else {
+        return;
+    };
+    let mut temp_path = path.clone().into_os_string();
+    temp_path.push(".tmp");
+    let written = std::fs::write(&temp_path, format!("{}\n", name))
+        .and_then(|()| std::fs::rename(&temp_path, &path));
+    if let Err(err) = written {
+        warnings::warn(format!("Could not write notify_file {}: {}", path.display(), err));
+    }
+}
+
+/// Run the profile's `on_switch` command after it was applied. A failing hook
+/// doesn't undo the switch, so it's reported as a warning.
+fn run_switch_hook(config: &Config, name: &str) {
+    let Some(command) = config.get_profile(name).and_then(|profile| profile.on_switch.as_deref()) else {
+        return;
+    };
+    if config.no_hooks {
+        return;
+    }
+    match std::process::Command::new("sh").args(["-c", command]).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => warnings::warn(format!("on_switch hook for '{}' failed ({})", name, status)),
+        Err(err) => warnings::warn(format!("Could not run on_switch hook for '{}': {}", name, err)),
+    }
+}
+
+fn read_signing_key_file(path: &Path) -> Result<String> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read signing key file '{}'", path.display()))?;
+    let key = content.trim();
+    if key.is_empty() {
+        anyhow::bail!("Signing key file '{}' is empty", path.display());
+    }
+    Ok(key.to_string())
+}
+
+fn parse_env_var(raw: &str) -> Result<(String, String), String> {
+    let (key, value) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", raw))?;
+    if !shell::is_valid_env_name(key) {
+        return Err(format!("'{}' is not a valid environment variable name", key));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// What `gsw auto` decided to do for the current directory
+enum AutoOutcome {
+    /// Not inside a git repository
+    NotARepo,
+    /// No .gswitch file applies here
+    NoProfile,
+    /// A .gswitch file names a profile that isn't configured
+    MissingProfile(String),
+    /// The effective identity already is the profile's, whether from a local
+    /// override or the global config, so nothing was written
+    Unchanged(String),
+    /// The profile was written to the repo's local config
+    Switched(String),
+}
+
+impl AutoOutcome {
+    /// Stable token printed by `gsw auto --porcelain`
+    fn porcelain(&self) -> String {
+        match self {
+            AutoOutcome::NotARepo => "not-a-repo".to_string(),
+            AutoOutcome::NoProfile => "no-profile".to_string(),
+            AutoOutcome::MissingProfile(name) => format!("missing-profile {}", name),
+            AutoOutcome::Unchanged(name) => format!("unchanged {}", name),
+            AutoOutcome::Switched(name) => format!("switched {}", name),
+        }
+    }
+
+    /// Exit code paired with each porcelain token; 1 stays reserved for
+    /// errors and `--strict` failures
+    fn exit_code(&self) -> i32 {
+        match self {
+            AutoOutcome::Switched(_) | AutoOutcome::Unchanged(_) => 0,
+            AutoOutcome::NoProfile => 4,
+            AutoOutcome::NotARepo => 2,
+            AutoOutcome::MissingProfile(_) => 3,
+        }
+    }
+}
+
+struct AutoResolution {
+    source: Option<ProfileSource>,
+    outcome: AutoOutcome,
+}
+
+impl AutoResolution {
+    /// Append one line for this decision, recording `dir` (or the cwd) as `cwd`
+    fn append_to_log(&self, log_path: &Path, dir: Option<&Path>) -> Result<()> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let cwd = std::env::current_dir()
+            .map(|cwd| dir.map_or_else(|| cwd.clone(), |dir| cwd.join(dir)))
+            .map(|d| d.display().to_string())
+            .unwrap_or_default();
+        let source = self.source
+            .as_ref()
+            .map(|source| source.to_string())
+            .unwrap_or_else(|| "none".to_string());
+        let (profile, applied) = match &self.outcome {
+            AutoOutcome::NotARepo | AutoOutcome::NoProfile => ("none", false),
+            AutoOutcome::MissingProfile(name) | AutoOutcome::Unchanged(name) => (name.as_str(), false),
+            AutoOutcome::Switched(name) => (name.as_str(), true),
+        };
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_path)
+            .context("Failed to open auto log file")?;
+        writeln!(
+            file,
+            "{} cwd={} source={} profile={} applied={}",
+            timestamp, cwd, source, profile, applied
+        )
+        .context("Failed to write auto log file")
+    }
+}
+
+/// The profile `auto` applies at `dir`: the repo's .gswitch first, then
+/// `GSWITCH_DEFAULT_DOTFILE`, `url_rules` and, with `home_fallback`, `~/.gswitch`
+fn resolve_auto_profile(config: &Config, file: Option<&Path>, no_boundary: bool, dir: Option<&Path>) -> Result<Resolution> {
+    // The cache only watches files inside the repo, so unbounded lookups skip it
+    let resolution = match dirs::home_dir() {
+        Some(home) if no_boundary => resolve::resolve_profile_unbounded_in_dir(file, dir, &home)?,
+        _ => cache::resolve_profile_cached(file, dir)?,
+    };
+    let default_dotfile = std::env::var_os(resolve::DEFAULT_DOTFILE_ENV).map(PathBuf::from);
+    let resolution = resolve::resolve_from_default_dotfile(resolution, default_dotfile.as_deref());
+    let resolution = resolve::resolve_from_url_rules(resolution, config, dir);
+    Ok(match dirs::home_dir() {
+        Some(home) if config.home_fallback => resolve::resolve_from_home_dotfile(resolution, &home),
+        _ => resolution,
+    })
+}
+
+/// Whether the effective identity at `dir` already is `profile`'s. One that
+/// matches (often the global default) needs no local override, which would only
+/// shadow later global changes; `always_local` still asks for the override unless
+/// the repo's own local identity matches.
+fn identity_up_to_date(profile: &GitProfile, always_local: bool, dir: Option<&Path>) -> bool {
+    let matches = |identity: &GitProfile| identity.email == profile.email && identity.name == profile.name;
+    git::get_current_git_config_in_dir(dir).is_ok_and(|current| matches(&current))
+        && (!always_local || git::get_scoped_git_config_in_dir("--local", dir).is_ok_and(|local| matches(&local)))
+}
+
+fn resolve_auto(
+    config: &Config,
+    file: Option<&Path>,
+    no_boundary: bool,
+    always_local: bool,
+    dir: Option<&Path>,
+) -> Result<AutoResolution> {
+    let (profile_name, source) = match resolve_auto_profile(config, file, no_boundary, dir)? {
+        Resolution::NotARepo => return Ok(AutoResolution { source: None, outcome: AutoOutcome::NotARepo }),
+        Resolution::NoProfile(source) => return Ok(AutoResolution { source, outcome: AutoOutcome::NoProfile }),
+        Resolution::Found(profile_name, source) => (profile_name, source),
+    };
+    // A committed .gswitch may use an alias; report and record the profile's own name
+    let profile_name = config.canonical_name(&profile_name).to_string();
+
+    // Check if we have the profile in config
+    let Some(profile) = config.get_profile(&profile_name) else {
+        return Ok(AutoResolution {
+            source: Some(source),
+            outcome: AutoOutcome::MissingProfile(profile_name),
+        });
+    };
+
+    // Signing follows the repo's remote, whether or not the identity needs writing
+    git::apply_sign_hosts_in_dir(profile, dir)?;
+
+    if identity_up_to_date(profile, always_local, dir) {
+        return Ok(AutoResolution {
+            source: Some(source),
+            outcome: AutoOutcome::Unchanged(profile_name),
+        });
+    }
+
+    // Only set git config if we actually need to change it
+    git::set_git_config_in_dir(profile, false, dir, None)?;
+    Ok(AutoResolution {
+        source: Some(source),
+        outcome: AutoOutcome::Switched(profile_name),
+    })
+}
+
+/// Report problems with the setup, repairing what can be with `fix`. `unreadable`
+/// lists the parts of config.toml that failed to load.
+fn doctor(config: &mut Config, fix: bool, unreadable: Vec<String>) -> Result<()> {
+    let mut problems = doctor_checks(config)?;
+    if fix && !problems.is_empty() {
+        let config_dir = Config::config_path()?.parent().map(Path::to_path_buf);
+        if let Some(dir) = config_dir.filter(|dir| !dir.is_dir()) {
+            std::fs::create_dir_all(&dir).context("Failed to create config directory")?;
+            println!("Fixed: created {}", dir.display());
+        }
+        // Cleaning a partly loaded config would act on the profiles it's missing
+        let changes = if unreadable.is_empty() { config.clean() } else { Vec::new() };
+        for change in &changes {
+            println!("Fixed: {}", change);
+        }
+        if !changes.is_empty() {
+            config.save()?;
+        }
+        problems = doctor_checks(config)?;
+    }
+    problems.splice(0..0, unreadable);
+
+    // The key may be on a smartcard that's unplugged right now, so this only warns
+    for (name, profile) in &config.profiles {
+        if let Some(key) = &profile.signing_key
+            && !keys::signing_key_available(key, profile.signing_format.as_deref())
+        {
+            warnings::warn(format!("Profile '{}' can't sign: key {} not found", name, key));
+        }
+    }
+
+    if problems.is_empty() {
+        println!("No problems found");
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("  {}", problem);
+    }
+    println!("{} problem(s) found", problems.len());
+    if !fix {
+        println!("Run `gsw doctor --fix` to repair what can be fixed safely");
+    }
+    std::process::exit(1);
+}
+
+/// Problems `gsw doctor` reports. Duplicates and invalid values are left for the
+/// user, since choosing a fix for them would be guesswork.
+fn doctor_checks(config: &Config) -> Result<Vec<String>> {
+    let mut problems = Vec::new();
+    if let Some(dir) = Config::config_path()?.parent()
+        && !dir.is_dir()
+    {
+        problems.push(format!("config directory {} doesn't exist", dir.display()));
+    }
+    if let Some(previous) = &config.previous_profile
+        && config.get_profile(previous).is_none()
+    {
+        problems.push(format!("{}: previous_profile references a profile that doesn't exist", previous));
+    }
+    for problem in config.validate(false) {
+        problems.push(format!("{}: {} {}", problem.profile, problem.field, problem.problem));
+    }
+    Ok(problems)
+}
+
+/// Make the profile a .gswitch `global <profile>` line names the global default,
+/// unless it already is
+fn apply_dotfile_global(config: &mut Config, dotfile_path: &Path) -> Result<()> {
+    let Some(name) = dotfile::read_dotfile_spec(dotfile_path).ok().and_then(|spec| spec.global) else {
+        return Ok(());
+    };
+    let name = config.canonical_name(&name).to_string();
+    if config.current_profile.as_deref() == Some(name.as_str()) {
+        return Ok(());
+    }
+    let Some(profile) = config.get_profile(&name) else {
+        warnings::warn(format!("Global profile '{}' in {} not found", name, dotfile_path.display()));
+        return Ok(());
+    };
+    git::set_git_config(profile, true)?;
+    config.set_current_profile(name);
+    config.save()
+}
+
+/// How a repo's identity compares to the profile `auto` would apply to it
+enum ReconcileOutcome {
+    /// Nothing names a profile for this repo
+    NoProfile,
+    /// The named profile isn't configured
+    MissingProfile(String),
+    InSync(String),
+    /// The local identity differs (and was switched when applying)
+    Drifted(String),
+}
+
+fn reconcile_repo(config: &Config, repo: &Path, apply: bool) -> Result<ReconcileOutcome> {
+    let profile_name = match resolve_auto_profile(config, None, config.no_boundary, Some(repo))? {
+        Resolution::Found(profile_name, _) => config.canonical_name(&profile_name).to_string(),
+        Resolution::NotARepo | Resolution::NoProfile(_) => return Ok(ReconcileOutcome::NoProfile),
+    };
+    let Some(profile) = config.get_profile(&profile_name) else {
+        return Ok(ReconcileOutcome::MissingProfile(profile_name));
+    };
+
+    // In sync exactly when `auto` would leave the repo alone
+    if identity_up_to_date(profile, false, Some(repo)) {
+        return Ok(ReconcileOutcome::InSync(profile_name));
+    }
+
+    if apply {
+        git::set_git_config_in_dir(profile, false, Some(repo), None)?;
+        git::apply_sign_hosts_in_dir(profile, Some(repo))?;
+    }
+    Ok(ReconcileOutcome::Drifted(profile_name))
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use crate::config::{Config, GitProfile};
+
+/// How long to wait on the policy server before falling back to the cache
+#[cfg(feature = "policy")]
+const FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// An organization's approved profile set, as served from `policy_url`
+#[derive(Debug, Deserialize)]
+struct Bundle {
+    profiles: HashMap<String, GitProfile>,
+}
+
+/// Where the last successfully fetched bundle came from
+#[derive(Debug, PartialEq)]
+pub enum SyncSource {
+    Network,
+    /// The server couldn't be reached; the cached bundle was used instead
+    Cache(String),
+}
+
+/// The last fetched bundle is kept next to the config file for offline use
+pub fn cache_path() -> Result<PathBuf> {
+    let config_path = Config::config_path()?;
+    let dir = config_path.parent().context("Config path has no parent directory")?;
+    Ok(dir.join("policy_cache"))
+}
+
+/// Parse a bundle as JSON when it looks like an object, otherwise as TOML
+pub fn parse_bundle(content: &str) -> Result<HashMap<String, GitProfile>> {
+    let bundle: Bundle = if content.trim_start().starts_with('{') {
+        serde_json::from_str(content).context("Failed to parse JSON policy bundle")?
+    } else {
+        toml::from_str(content).context("Failed to parse TOML policy bundle")?
+    };
+
+    let check = Config {
+        profiles: bundle.profiles.clone(),
+        ..Default::default()
+    };
+    if let Some(problem) = check.validate(false).first() {
+        bail!(
+            "Policy bundle is invalid: {}: {} {}",
+            problem.profile,
+            problem.field,
+            problem.problem
+        );
+    }
+
+    Ok(bundle.profiles)
+}
+
+#[cfg(feature = "policy")]
+fn fetch(url: &str) -> Result<String> {
+    let agent = ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build();
+    agent
+        .get(url)
+        .call()
+        .with_context(|| format!("Failed to fetch {}", url))?
+        .into_string()
+        .context("Failed to read policy bundle")
+}
+
+#[cfg(not(feature = "policy"))]
+fn fetch(_url: &str) -> Result<String> {
+    bail!("gsw was built without the `policy` feature")
+}
+
+/// Fetch, validate and cache the bundle at `url`, then install it as managed profiles.
+/// When the server can't be reached the cached bundle is used instead.
+pub fn sync(config: &mut Config, url: &str) -> Result<(usize, SyncSource)> {
+    let cache = cache_path()?;
+
+    let (profiles, source) = match fetch(url) {
+        Ok(content) => {
+            let profiles = parse_bundle(&content)?;
+            if let Some(parent) = cache.parent() {
+                std::fs::create_dir_all(parent).context("Failed to create config directory")?;
+            }
+            std::fs::write(&cache, &content).context("Failed to write policy cache")?;
+            (profiles, SyncSource::Network)
+        }
+        Err(err) => {
+            let Ok(content) = std::fs::read_to_string(&cache) else {
+                return Err(err.context("No cached policy bundle to fall back to"));
+            };
+            (parse_bundle(&content)?, SyncSource::Cache(format!("{:#}", err)))
+        }
+    };
+
+    Ok((config.replace_managed_profiles(profiles), source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_toml_bundle() {
+        let profiles = parse_bundle(
+            "[profiles.work]\nname = \"Work User\"\nemail = \"work@corp.example\"\n",
+        )
+        .unwrap();
+        assert_eq!(profiles["work"].email, "work@corp.example");
+    }
+
+    #[test]
+    fn test_parse_json_bundle() {
+        let profiles = parse_bundle(
+            r#"{"profiles": {"work": {"name": "Work User", "email": "work@corp.example"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(profiles["work"].name, "Work User");
+    }
+
+    #[test]
+    fn test_parse_bundle_rejects_invalid_profiles() {
+        let err = parse_bundle("[profiles.work]\nname = \"Work User\"\nemail = \"not-an-email\"\n")
+            .unwrap_err();
+        assert!(err.to_string().contains("Policy bundle is invalid"));
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::{Result, bail};
+use crate::config::GitProfile;
+use crate::git;
+
+/// A repository on another machine, from `user@host:path`
+#[derive(Debug, PartialEq)]
+pub struct RemoteRepo {
+    /// What ssh connects to (`user@host` or a host alias)
+    pub host: String,
+    pub path: String,
+}
+
+impl RemoteRepo {
+    pub fn parse(target: &str) -> Result<Self> {
+        match target.split_once(':') {
+            Some((host, path)) if !host.is_empty() && !path.is_empty() => Ok(RemoteRepo {
+                host: host.to_string(),
+                path: path.to_string(),
+            }),
+            _ => bail!("Expected a remote repo as user@host:path, got '{}'", target),
+        }
+    }
+
+    /// The shell script run on the remote host: the same `git config` writes
+    /// applying the profile locally would make, stopping at the first failure
+    pub fn script(&self, profile: &GitProfile) -> String {
+        let commands: Vec<String> = git::planned_changes(profile, None)
+            .iter()
+            .map(|change| change.shell_command(&["git", "-C", &self.path], "--local"))
+            .collect();
+        commands.join(" && ")
+    }
+
+    /// Arguments to `ssh` that apply the profile to this repo
+    pub fn ssh_args(&self, profile: &GitProfile) -> Vec<String> {
+        vec!["--".to_string(), self.host.clone(), self.script(profile)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_remote_repo() {
+        assert_eq!(
+            RemoteRepo::parse("deploy@build-01:/srv/app").unwrap(),
+            RemoteRepo { host: "deploy@build-01".to_string(), path: "/srv/app".to_string() }
+        );
+        assert!(RemoteRepo::parse("build-01").is_err());
+        assert!(RemoteRepo::parse("build-01:").is_err());
+    }
+
+    #[test]
+    fn test_ssh_args() {
+        let repo = RemoteRepo::parse("deploy@build-01:/srv/my app").unwrap();
+        let profile = GitProfile {
+            name: "Ops Bot".to_string(),
+            email: "ops@example.com".to_string(),
+            ..Default::default()
+        };
+        let args = repo.ssh_args(&profile);
+        assert_eq!(args[..2], ["--", "deploy@build-01"]);
+        assert!(args[2].starts_with(
+            "git -C '/srv/my app' config --local user.name 'Ops Bot' && \
+             git -C '/srv/my app' config --local user.email ops@example.com && \
+             { git -C '/srv/my app' config --local --unset-all gpg.format || test $? -eq 5; }"
+        ));
+        assert!(!args[2].contains("user.signingkey"));
+    }
+}
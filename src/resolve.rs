@@ -0,0 +1,290 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result, bail};
+use crate::config::Config;
+use crate::{dotfile, git};
+
+/// Local git config key that can pin a profile without committing a .gswitch file
+pub const GIT_CONFIG_PROFILE_KEY: &str = "gswitch.profile";
+
+/// Environment variable naming a .gswitch file that applies to every repo without
+/// one of its own, e.g. in CI containers where each checkout is fresh
+pub const DEFAULT_DOTFILE_ENV: &str = "GSWITCH_DEFAULT_DOTFILE";
+
+/// Where a resolved profile name came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProfileSource {
+    /// A .gswitch file, found by discovery or given explicitly
+    File(PathBuf),
+    /// The repo's local `gswitch.profile` git config value
+    GitConfig,
+    /// A `url_rules` pattern matching the repo's origin URL
+    UrlRule(String),
+}
+
+impl fmt::Display for ProfileSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileSource::File(path) => write!(f, "{}", path.display()),
+            ProfileSource::GitConfig => write!(f, "git config {}", GIT_CONFIG_PROFILE_KEY),
+            ProfileSource::UrlRule(pattern) => write!(f, "url rule {}", pattern),
+        }
+    }
+}
+
+/// Outcome of looking up which profile applies to a directory
+#[derive(Debug, PartialEq)]
+pub enum Resolution {
+    /// Not inside a git repository
+    NotARepo,
+    /// Inside a repo, but nothing names a profile (the source is set if it was empty)
+    NoProfile(Option<ProfileSource>),
+    /// A profile name and where it came from
+    Found(String, ProfileSource),
+}
+
+/// Report a .gswitch file that was found but can't be used, e.g. a corrupted one
+fn warn_unusable(path: &Path, err: &anyhow::Error) {
+    crate::warnings::warn(format!("Ignoring {}: {:#}", path.display(), err));
+}
+
+/// Work out which profile applies to `dir` (or the current directory).
+///
+/// Resolution order: an explicit `file`, then the repo's local
+/// `gswitch.profile` git config, then the nearest .gswitch file. A .gswitch
+/// with an `only-dotfile` line skips the git config (and, in `gsw auto`, `url_rules`).
+pub fn resolve_profile_in_dir<P: AsRef<Path>>(file: Option<&Path>, dir: Option<P>) -> Result<Resolution> {
+    if let Some(path) = file {
+        // An explicit file bypasses discovery, so validate it up front
+        if !path.is_file() {
+            bail!("Profile file '{}' does not exist", path.display());
+        }
+        if !git::is_git_repo_in_dir(dir.as_ref()) {
+            return Ok(Resolution::NotARepo);
+        }
+        let profile_name = dotfile::read_profile_from_dotfile(path)
+            .with_context(|| format!("Failed to read profile from '{}'", path.display()))?;
+        return Ok(Resolution::Found(profile_name, ProfileSource::File(path.to_path_buf())));
+    }
+
+    // An `only-dotfile` line makes the dotfile the sole source, so look for it first
+    let dotfile_path = dotfile::find_dotfile_in_dir(dir.as_ref());
+    let spec = dotfile_path.as_ref().and_then(|path| {
+        dotfile::read_dotfile_spec(path).map_err(|err| warn_unusable(path, &err)).ok()
+    });
+    let only_dotfile = spec.as_ref().is_some_and(|spec| spec.only_dotfile);
+
+    if !only_dotfile
+        && let Some(profile_name) = git::get_config_value_in_dir(GIT_CONFIG_PROFILE_KEY, dir.as_ref())
+        && !profile_name.is_empty()
+    {
+        return Ok(Resolution::Found(profile_name, ProfileSource::GitConfig));
+    }
+
+    // Finding a dotfile implies we're inside a git repo
+    let Some(dotfile_path) = dotfile_path else {
+        if git::is_git_repo_in_dir(dir.as_ref()) {
+            return Ok(Resolution::NoProfile(None));
+        }
+        return Ok(Resolution::NotARepo);
+    };
+    match spec.and_then(|spec| spec.local) {
+        Some(profile_name) => Ok(Resolution::Found(profile_name, ProfileSource::File(dotfile_path))),
+        None => Ok(Resolution::NoProfile(Some(ProfileSource::File(dotfile_path)))),
+    }
+}
+
+/// When nothing in the repo names a profile, use the profile in `default_dotfile`
+/// (the file named by `GSWITCH_DEFAULT_DOTFILE`), if it names one
+pub fn resolve_from_default_dotfile(resolution: Resolution, default_dotfile: Option<&Path>) -> Resolution {
+    if resolution != Resolution::NoProfile(None) {
+        return resolution;
+    }
+    let Some(path) = default_dotfile else {
+        return resolution;
+    };
+    match dotfile::read_profile_from_dotfile(path) {
+        Ok(profile_name) => Resolution::Found(profile_name, ProfileSource::File(path.to_path_buf())),
+        Err(err) => {
+            crate::warnings::warn(format!("Ignoring {} {}: {:#}", DEFAULT_DOTFILE_ENV, path.display(), err));
+            resolution
+        }
+    }
+}
+
+/// With `home_fallback`, the last resort when nothing else names a profile:
+/// the .gswitch in `home` itself, however far the repo is from it
+pub fn resolve_from_home_dotfile(resolution: Resolution, home: &Path) -> Resolution {
+    if resolution != Resolution::NoProfile(None) {
+        return resolution;
+    }
+    let Some(path) = dotfile::dotfile_in(home) else {
+        return resolution;
+    };
+    match dotfile::read_profile_from_dotfile(&path) {
+        Ok(profile_name) => Resolution::Found(profile_name, ProfileSource::File(path)),
+        Err(err) => {
+            warn_unusable(&path, &err);
+            resolution
+        }
+    }
+}
+
+/// When nothing in the repo names a profile, try the config's `url_rules`
+/// against the `origin` remote URL of the repo at `dir`
+pub fn resolve_from_url_rules<P: AsRef<Path>>(resolution: Resolution, config: &Config, dir: Option<P>) -> Resolution {
+    if resolution != Resolution::NoProfile(None) {
+        return resolution;
+    }
+    git::get_config_value_in_dir("remote.origin.url", dir)
+        .and_then(|url| config.match_url_rules(&url))
+        .map_or(resolution, |(profile, pattern)| {
+            Resolution::Found(profile, ProfileSource::UrlRule(pattern.to_string()))
+        })
+}
+
+/// Like `resolve_profile_in_dir`, but when the repo itself names no profile, also use
+/// the nearest .gswitch above the repo root, up to `stop`
+pub fn resolve_profile_unbounded_in_dir<P: AsRef<Path>>(
+    file: Option<&Path>,
+    dir: Option<P>,
+    stop: &Path,
+) -> Result<Resolution> {
+    let resolution = resolve_profile_in_dir(file, dir.as_ref())?;
+    if file.is_some() || resolution != Resolution::NoProfile(None) {
+        return Ok(resolution);
+    }
+
+    let Some(dotfile_path) = dotfile::find_dotfile_above_root(dir.as_ref(), stop) else {
+        return Ok(resolution);
+    };
+    match dotfile::read_profile_from_dotfile(&dotfile_path) {
+        Ok(profile_name) => Ok(Resolution::Found(profile_name, ProfileSource::File(dotfile_path))),
+        Err(err) => {
+            warn_unusable(&dotfile_path, &err);
+            Ok(Resolution::NoProfile(Some(ProfileSource::File(dotfile_path))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_resolve_per_host_dotfile() {
+        with_git_repo(|repo| {
+            let hostname = crate::system::hostname().unwrap();
+            let dotfile = ProfileSource::File(repo.join(".gswitch"));
+
+            repo.create_file(".gswitch", &format!("host:{} work\npersonal\n", hostname)).unwrap();
+            let resolution = resolve_profile_in_dir(None, Some(repo.path())).unwrap();
+            assert_eq!(resolution, Resolution::Found("work".to_string(), dotfile.clone()));
+
+            repo.create_file(".gswitch", "host:some-other-machine work\npersonal\n").unwrap();
+            let resolution = resolve_profile_in_dir(None, Some(repo.path())).unwrap();
+            assert_eq!(resolution, Resolution::Found("personal".to_string(), dotfile.clone()));
+
+            repo.create_file(".gswitch", "host:some-other-machine work\n").unwrap();
+            let resolution = resolve_profile_in_dir(None, Some(repo.path())).unwrap();
+            assert_eq!(resolution, Resolution::NoProfile(Some(dotfile)));
+        });
+    }
+
+    #[test]
+    fn test_resolve_from_dotfile() {
+        with_git_repo(|repo| {
+            repo.create_file(".gswitch", "work\n").unwrap();
+
+            let resolution = resolve_profile_in_dir(None, Some(repo.path())).unwrap();
+            assert_eq!(
+                resolution,
+                Resolution::Found("work".to_string(), ProfileSource::File(repo.join(".gswitch")))
+            );
+        });
+    }
+
+    #[test]
+    fn test_resolve_git_config_takes_precedence_over_dotfile() {
+        with_git_repo(|repo| {
+            repo.create_file(".gswitch", "work\n").unwrap();
+            Command::new("git")
+                .args(["config", "--local", GIT_CONFIG_PROFILE_KEY, "personal"])
+                .current_dir(repo.path())
+                .output()
+                .unwrap();
+
+            let resolution = resolve_profile_in_dir(None, Some(repo.path())).unwrap();
+            assert_eq!(resolution, Resolution::Found("personal".to_string(), ProfileSource::GitConfig));
+        });
+    }
+
+    #[test]
+    fn test_resolve_only_dotfile_ignores_git_config() {
+        with_git_repo(|repo| {
+            repo.create_file(".gswitch", "work\nonly-dotfile\n").unwrap();
+            Command::new("git")
+                .args(["config", "--local", GIT_CONFIG_PROFILE_KEY, "personal"])
+                .current_dir(repo.path())
+                .output()
+                .unwrap();
+
+            let resolution = resolve_profile_in_dir(None, Some(repo.path())).unwrap();
+            assert_eq!(
+                resolution,
+                Resolution::Found("work".to_string(), ProfileSource::File(repo.join(".gswitch")))
+            );
+
+            // Without a profile line there's nothing to apply, and url_rules stay out of it
+            repo.create_file(".gswitch", "only-dotfile\n").unwrap();
+            let no_profile = Resolution::NoProfile(Some(ProfileSource::File(repo.join(".gswitch"))));
+            let resolution = resolve_profile_in_dir(None, Some(repo.path())).unwrap();
+            assert_eq!(resolution, no_profile);
+            let config = Config {
+                url_rules: vec![crate::config::UrlRule { pattern: ".".to_string(), profile: Some("oss".to_string()) }],
+                ..Config::default()
+            };
+            assert_eq!(resolve_from_url_rules(resolution, &config, Some(repo.path())), no_profile);
+        });
+    }
+
+    #[test]
+    fn test_resolve_from_default_dotfile() {
+        with_temp_dir(|temp_dir| {
+            let default_dotfile = temp_dir.path().join("ci.gswitch");
+            std::fs::write(&default_dotfile, "ci\n").unwrap();
+            let found = Resolution::Found("ci".to_string(), ProfileSource::File(default_dotfile.clone()));
+            assert_eq!(resolve_from_default_dotfile(Resolution::NoProfile(None), Some(&default_dotfile)), found);
+            assert_eq!(resolve_from_default_dotfile(Resolution::NoProfile(None), None), Resolution::NoProfile(None));
+
+            // A repo's own dotfile, and a repo-less directory, keep their resolution
+            let repo_dotfile = || Resolution::Found("work".to_string(), ProfileSource::File(temp_dir.path().join(".gswitch")));
+            assert_eq!(resolve_from_default_dotfile(repo_dotfile(), Some(&default_dotfile)), repo_dotfile());
+            assert_eq!(resolve_from_default_dotfile(Resolution::NotARepo, Some(&default_dotfile)), Resolution::NotARepo);
+        });
+    }
+
+    #[test]
+    fn test_resolve_from_home_dotfile() {
+        with_temp_dir(|home| {
+            assert_eq!(resolve_from_home_dotfile(Resolution::NoProfile(None), home.path()), Resolution::NoProfile(None));
+
+            std::fs::write(home.path().join(".gswitch"), "personal\n").unwrap();
+            let found = Resolution::Found("personal".to_string(), ProfileSource::File(home.path().join(".gswitch")));
+            assert_eq!(resolve_from_home_dotfile(Resolution::NoProfile(None), home.path()), found);
+            assert_eq!(resolve_from_home_dotfile(Resolution::NotARepo, home.path()), Resolution::NotARepo);
+        });
+    }
+
+    #[test]
+    fn test_resolve_no_profile_and_not_a_repo() {
+        with_git_repo(|repo| {
+            assert_eq!(resolve_profile_in_dir(None, Some(repo.path())).unwrap(), Resolution::NoProfile(None));
+        });
+        with_temp_dir(|temp_dir| {
+            assert_eq!(resolve_profile_in_dir(None, Some(temp_dir.path())).unwrap(), Resolution::NotARepo);
+        });
+    }
+}
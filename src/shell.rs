@@ -0,0 +1,134 @@
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use clap::ColorChoice;
+use clap_complete::Shell;
+
+/// Header written at the top of installed completion files so reinstalls can
+/// tell our files apart from ones the user manages
+pub const COMPLETION_MARKER: &str = "# Generated by gsw completions";
+
+/// Detect the user's shell from `$SHELL`
+pub fn detect_shell() -> Option<Shell> {
+    let shell = std::env::var("SHELL").ok()?;
+    let name = shell.rsplit('/').next()?;
+    name.parse().ok()
+}
+
+/// Detect the user's shell from `$SHELL` among those `gsw activate` supports
+pub fn detect_activation_shell() -> Option<&'static str> {
+    let shell = std::env::var("SHELL").ok()?;
+    match shell.rsplit('/').next()? {
+        "bash" => Some("bash"),
+        "zsh" => Some("zsh"),
+        "fish" => Some("fish"),
+        "nu" | "nushell" => Some("nushell"),
+        _ => None,
+    }
+}
+
+/// Conventional per-user location for a shell's completion script
+pub fn completion_install_path(shell: Shell) -> Option<PathBuf> {
+    let home = dirs::home_dir()?;
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".local/share"));
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".config"));
+
+    match shell {
+        Shell::Bash => Some(data_home.join("bash-completion/completions/gsw")),
+        Shell::Zsh => {
+            let zdotdir = std::env::var_os("ZDOTDIR").map(PathBuf::from).unwrap_or(home);
+            Some(zdotdir.join(".zfunc/_gsw"))
+        }
+        Shell::Fish => Some(config_home.join("fish/completions/gsw.fish")),
+        _ => None,
+    }
+}
+
+/// Environment variable the `gsw activate` scripts export, so `--check` can tell
+/// whether the integration is loaded in the current shell
+pub const HOOK_ENV: &str = "GSWITCH_HOOK";
+
+/// Startup file where the README tells users to load `gsw activate` for a shell
+pub fn rc_file(shell: &str) -> Option<PathBuf> {
+    let home = dirs::home_dir()?;
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".config"));
+
+    match shell {
+        "bash" => Some(home.join(".bashrc")),
+        "zsh" => {
+            let zdotdir = std::env::var_os("ZDOTDIR").map(PathBuf::from).unwrap_or(home);
+            Some(zdotdir.join(".zshrc"))
+        }
+        "fish" => Some(config_home.join("fish/config.fish")),
+        "nushell" => Some(config_home.join("nushell/config.nu")),
+        _ => None,
+    }
+}
+
+/// Quote a value for POSIX shells, leaving simple words untouched
+pub fn quote(value: &str) -> String {
+    let is_simple = !value.is_empty()
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || "-_./@:+=,%".contains(c));
+    if is_simple {
+        return value.to_string();
+    }
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Whether to emit ANSI colors: `auto` colors a terminal unless NO_COLOR is set
+pub fn use_color(choice: ColorChoice) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && std::io::stdout().is_terminal()
+        }
+    }
+}
+
+/// Whether a name can be used as a shell environment variable
+pub fn is_valid_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Render an `export NAME=value` line
+pub fn export_line(name: &str, value: &str) -> String {
+    format!("export {}={}", name, quote(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("simple@example.com"), "simple@example.com");
+        assert_eq!(quote("Test User"), "'Test User'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+        assert_eq!(quote(""), "''");
+    }
+
+    #[test]
+    fn test_completion_install_path_for_fish() {
+        let path = completion_install_path(Shell::Fish).unwrap();
+        assert!(path.ends_with("fish/completions/gsw.fish"));
+        assert!(completion_install_path(Shell::PowerShell).is_none());
+    }
+
+    #[test]
+    fn test_is_valid_env_name() {
+        assert!(is_valid_env_name("HTTP_PROXY"));
+        assert!(is_valid_env_name("_private"));
+        assert!(!is_valid_env_name("1ST"));
+        assert!(!is_valid_env_name("WITH-DASH"));
+        assert!(!is_valid_env_name(""));
+    }
+}
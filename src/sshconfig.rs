@@ -0,0 +1,110 @@
+/// A `Host` alias from an OpenSSH client config and the key it authenticates with
+#[derive(Debug, PartialEq)]
+pub struct SshHost {
+    pub alias: String,
+    pub identity_file: String,
+}
+
+/// Collect every concrete `Host` alias that sets an `IdentityFile`.
+///
+/// Only the subset gswitch needs is understood: wildcard patterns are skipped,
+/// `Match` blocks end the current host, and `Include` directives are ignored.
+pub fn parse_ssh_config(content: &str) -> Vec<SshHost> {
+    let mut hosts = Vec::new();
+    let mut aliases: Vec<String> = Vec::new();
+    let mut identity_file: Option<String> = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((keyword, value)) = split_directive(line) else {
+            continue;
+        };
+
+        match keyword.to_ascii_lowercase().as_str() {
+            "host" | "match" => {
+                flush(&mut hosts, &mut aliases, identity_file.take());
+                if keyword.eq_ignore_ascii_case("host") {
+                    aliases = value
+                        .split_whitespace()
+                        .filter(|pattern| !pattern.contains(['*', '?', '!']))
+                        .map(str::to_string)
+                        .collect();
+                }
+            }
+            // ssh uses the first IdentityFile it sees for a host
+            "identityfile" if identity_file.is_none() => {
+                identity_file = Some(value.trim_matches('"').to_string());
+            }
+            _ => {}
+        }
+    }
+    flush(&mut hosts, &mut aliases, identity_file);
+    hosts
+}
+
+fn flush(hosts: &mut Vec<SshHost>, aliases: &mut Vec<String>, identity_file: Option<String>) {
+    if let Some(identity_file) = identity_file {
+        hosts.extend(aliases.drain(..).map(|alias| SshHost {
+            alias,
+            identity_file: identity_file.clone(),
+        }));
+    }
+    aliases.clear();
+}
+
+/// Split `Keyword value` or `Keyword=value`
+fn split_directive(line: &str) -> Option<(&str, &str)> {
+    let end = line.find(|c: char| c.is_whitespace() || c == '=')?;
+    let (keyword, rest) = line.split_at(end);
+    let value = rest.trim_start().strip_prefix('=').unwrap_or(rest).trim();
+    Some((keyword, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ssh_config() {
+        let config = r#"
+Include ~/.ssh/config.d/*
+
+# Work account
+Host github-work
+    HostName github.com
+    User git
+    IdentityFile ~/.ssh/id_work
+    IdentityFile ~/.ssh/id_fallback
+
+Host github-oss gitlab-oss
+  HostName github.com
+  IdentityFile="~/.ssh/id oss"
+
+Host *
+    IdentityFile ~/.ssh/id_default
+
+Host no-key
+    HostName example.com
+
+Match host example.org
+    IdentityFile ~/.ssh/id_match
+"#;
+        assert_eq!(
+            parse_ssh_config(config),
+            vec![
+                SshHost { alias: "github-work".to_string(), identity_file: "~/.ssh/id_work".to_string() },
+                SshHost { alias: "github-oss".to_string(), identity_file: "~/.ssh/id oss".to_string() },
+                SshHost { alias: "gitlab-oss".to_string(), identity_file: "~/.ssh/id oss".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_empty_ssh_config() {
+        assert!(parse_ssh_config("").is_empty());
+        assert!(parse_ssh_config("IdentityFile ~/.ssh/id_global\n").is_empty());
+    }
+}
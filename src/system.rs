@@ -0,0 +1,31 @@
+use std::process::Command;
+
+/// This machine's hostname: from the kernel on Linux, otherwise from `hostname`
+pub fn hostname() -> Option<String> {
+    let name = std::fs::read_to_string("/proc/sys/kernel/hostname").ok().or_else(|| {
+        let output = Command::new("hostname").output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    })?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Whether `pattern` names this host, ignoring case; a short name also matches
+/// a fully qualified hostname (`laptop` matches `laptop.local`)
+pub fn hostname_matches(pattern: &str, hostname: &str) -> bool {
+    let short = hostname.split('.').next().unwrap_or(hostname);
+    pattern.eq_ignore_ascii_case(hostname) || pattern.eq_ignore_ascii_case(short)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hostname_matches() {
+        assert!(hostname_matches("work-laptop", "work-laptop"));
+        assert!(hostname_matches("Work-Laptop", "work-laptop.local"));
+        assert!(hostname_matches("work-laptop.local", "work-laptop.local"));
+        assert!(!hostname_matches("work", "work-laptop"));
+    }
+}
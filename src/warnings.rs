@@ -0,0 +1,18 @@
+use std::sync::Mutex;
+
+/// Warnings emitted by the running command, checked by `--strict` once it finishes
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Print an advisory warning to stderr and record it for `--strict`
+pub fn warn(message: impl Into<String>) {
+    let message = message.into();
+    eprintln!("Warning: {}", message);
+    if let Ok(mut warnings) = WARNINGS.lock() {
+        warnings.push(message);
+    }
+}
+
+/// Every warning recorded so far, clearing the list
+pub fn take() -> Vec<String> {
+    WARNINGS.lock().map(|mut warnings| std::mem::take(&mut *warnings)).unwrap_or_default()
+}
@@ -25,9 +25,59 @@ impl TestEnv {
     pub fn command(&self) -> Command {
         let mut cmd = Command::cargo_bin("gsw").expect("Failed to find gsw binary");
         cmd.env("XDG_CONFIG_HOME", &self.config_home);
+        cmd.env("XDG_STATE_HOME", self.temp_dir.path().join(".local/state"));
+        // Keep global git config changes inside the temp dir
+        cmd.env("GIT_CONFIG_GLOBAL", self.global_git_config());
+        cmd.env("GIT_CONFIG_NOSYSTEM", "1");
         cmd.current_dir(self.temp_dir.path());
         cmd
     }
+
+    pub fn global_git_config(&self) -> std::path::PathBuf {
+        self.temp_dir.path().join(".gitconfig")
+    }
+
+    /// Run git in the given directory with the same isolated global config as `command`
+    pub fn git<P: AsRef<std::path::Path>>(&self, dir: P, args: &[&str]) -> std::process::Output {
+        std::process::Command::new("git")
+            .args(args)
+            .env("GIT_CONFIG_GLOBAL", self.global_git_config())
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .current_dir(dir)
+            .output()
+            .expect("Failed to run git")
+    }
+
+    /// Initialize a git repo in the temp dir, mirroring `GitTestRepo` in the unit tests
+    pub fn init_git_repo(&self) {
+        self.init_git_repo_at(self.temp_dir.path());
+    }
+
+    /// Initialize a git repo at a path (created if needed) and seed a local identity
+    pub fn init_git_repo_at<P: AsRef<std::path::Path>>(&self, path: P) {
+        let path = path.as_ref();
+        std::fs::create_dir_all(path).expect("Failed to create repo directory");
+
+        let output = self.git(path, &["init"]);
+        assert!(output.status.success(), "Git init failed: {}", String::from_utf8_lossy(&output.stderr));
+
+        self.git(path, &["config", "user.name", "Test User"]);
+        self.git(path, &["config", "user.email", "test@example.com"]);
+    }
+
+    /// Read a git config value at the given scope (e.g. "--local") in a directory
+    pub fn git_config_at<P: AsRef<std::path::Path>>(&self, dir: P, scope: &str, key: &str) -> Option<String> {
+        let output = self.git(dir, &["config", scope, "--get", key]);
+        if !output.status.success() {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&output.stdout).trim_end_matches('\n').to_string())
+    }
+
+    /// Read a local git config value from the temp dir repo
+    pub fn local_git_config(&self, key: &str) -> Option<String> {
+        self.git_config_at(self.temp_dir.path(), "--local", key)
+    }
     
     pub fn create_gswitch_file<P: AsRef<std::path::Path>>(&self, path: P, content: &str) {
         let full_path = self.temp_dir.path().join(path);
@@ -38,7 +88,42 @@ impl TestEnv {
     }
     
     
+    /// Put a fake program on PATH ahead of the real ones, returning the new PATH
+    pub fn stub_program(&self, name: &str, script: &str) -> String {
+        use std::os::unix::fs::PermissionsExt;
+
+        let bin = self.temp_dir.path().join("bin");
+        std::fs::create_dir_all(&bin).expect("Failed to create stub directory");
+        let path = bin.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{}", script)).expect("Failed to write stub program");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+            .expect("Failed to make stub program executable");
+        format!("{}:{}", bin.display(), std::env::var("PATH").unwrap_or_default())
+    }
+
     pub fn change_to_temp_dir(&self) {
         std::env::set_current_dir(self.temp_dir.path()).expect("Failed to change to temp directory");
     }
-}
\ No newline at end of file
+}
+
+/// Serve `body` to a single HTTP request on a local port, returning its URL
+#[cfg(feature = "policy")]
+pub fn serve_once(body: &'static str) -> String {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Failed to bind test server");
+    let url = format!("http://{}/bundle.toml", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        if let Ok((mut stream, _)) = listener.accept() {
+            let mut request = [0u8; 4096];
+            let _ = stream.read(&mut request);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    url
+}
@@ -115,6 +115,36 @@ fn test_init_with_valid_profile() {
     assert_eq!(content.trim(), "test");
 }
 
+#[test]
+fn test_init_newline_style() {
+    let test_env = TestEnv::new();
+    let gswitch_path = test_env.temp_dir.path().join(".gswitch");
+
+    for name in ["work", "personal"] {
+        let mut cmd = test_env.command();
+        let email = format!("{}@example.com", name);
+        cmd.args(["add", name, "--user-name", "Test User", "--email", &email]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = test_env.command();
+    cmd.args(["init", "work", "--no-newline"]);
+    cmd.assert().success();
+    assert_eq!(std::fs::read_to_string(&gswitch_path).unwrap(), "work");
+
+    // Overwriting keeps the file's existing style
+    let mut cmd = test_env.command();
+    cmd.args(["init", "personal"]);
+    cmd.assert().success();
+    assert_eq!(std::fs::read_to_string(&gswitch_path).unwrap(), "personal");
+
+    std::fs::remove_file(&gswitch_path).unwrap();
+    let mut cmd = test_env.command();
+    cmd.args(["init", "work"]);
+    cmd.assert().success();
+    assert_eq!(std::fs::read_to_string(&gswitch_path).unwrap(), "work\n");
+}
+
 #[test]
 fn test_init_with_invalid_profile() {
     let test_env = TestEnv::new();
@@ -260,10 +290,2895 @@ fn test_current_format_email() {
 #[test]
 fn test_current_invalid_format() {
     let test_env = TestEnv::new();
+    test_env.init_git_repo();
     
     let mut cmd = test_env.command();
     cmd.args(["current", "--format", "invalid"]);
     cmd.assert()
         .success()
         .stdout(predicate::str::contains("Invalid format: invalid"));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_current_identify_names_matching_profile() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["current", "--identify"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("(no matching profile)"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Test User", "--email", "test@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["current", "--identify"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Name: Test User"))
+        .stdout(predicate::str::contains("(profile: work)"));
+}
+
+#[test]
+fn test_auto_log_appends_decision() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "test", "--user-name", "Auto User", "--email", "auto@example.com"]);
+    cmd.assert().success();
+    test_env.create_gswitch_file(".gswitch", "test\n");
+
+    let log_path = test_env.temp_dir.path().join("auto.log");
+    for _ in 0..2 {
+        let mut cmd = test_env.command();
+        cmd.args(["auto", "--log"]).arg(&log_path);
+        cmd.assert().success();
+    }
+
+    let log = std::fs::read_to_string(&log_path).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("cwd="));
+    assert!(lines[0].contains("source="));
+    assert!(lines[0].contains(".gswitch"));
+    assert!(lines[0].contains("profile=test"));
+    assert!(lines[0].contains("applied=true"));
+    assert!(lines[1].contains("applied=false"));
+}
+
+#[test]
+fn test_auto_porcelain_not_a_repo() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--porcelain"]);
+    cmd.assert()
+        .code(2)
+        .stdout("not-a-repo\n");
+}
+
+#[test]
+fn test_auto_porcelain_no_profile() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--porcelain"]);
+    cmd.assert()
+        .code(4)
+        .stdout("no-profile\n");
+}
+
+#[test]
+fn test_auto_porcelain_missing_profile() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    test_env.create_gswitch_file(".gswitch", "ghost\n");
+
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--porcelain"]);
+    cmd.assert()
+        .code(3)
+        .stdout("missing-profile ghost\n");
+}
+
+#[test]
+fn test_auto_porcelain_switched_then_unchanged() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    test_env.create_gswitch_file(".gswitch", "test\n");
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "test", "--user-name", "Auto User", "--email", "auto@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--porcelain"]);
+    cmd.assert()
+        .code(0)
+        .stdout("switched test\n");
+
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--porcelain"]);
+    cmd.assert()
+        .code(0)
+        .stdout("unchanged test\n");
+}
+
+#[test]
+fn test_init_infers_profile_from_local_identity() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "personal", "--user-name", "Home User", "--email", "home@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "work"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.arg("init");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Created .gswitch file with profile 'work'"));
+
+    let content = std::fs::read_to_string(test_env.temp_dir.path().join(".gswitch")).unwrap();
+    assert_eq!(content.trim(), "work");
+}
+
+#[test]
+fn test_auto_with_file_outside_repo_root() {
+    let test_env = TestEnv::new();
+    let repo = test_env.temp_dir.path().join("repo");
+    test_env.init_git_repo_at(&repo);
+    test_env.create_gswitch_file("ci/profile", "ci\n");
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "ci", "--user-name", "CI Bot", "--email", "ci@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.current_dir(&repo)
+        .args(["auto", "--porcelain", "--file"])
+        .arg(test_env.temp_dir.path().join("ci/profile"));
+    cmd.assert()
+        .success()
+        .stdout("switched ci\n");
+
+    assert_eq!(
+        test_env.git_config_at(&repo, "--local", "user.email"),
+        Some("ci@example.com".to_string())
+    );
+}
+
+#[test]
+fn test_auto_with_missing_file() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--file", "does-not-exist"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("does not exist"));
+
+    // The file exists but names a profile that isn't configured
+    test_env.create_gswitch_file("ci/profile", "ghost\n");
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--file", "ci/profile"]);
+    cmd.assert()
+        .code(3)
+        .stderr(predicate::str::contains("Profile 'ghost' specified in ci/profile not found"));
+}
+
+#[test]
+fn test_prompt_with_file() {
+    let test_env = TestEnv::new();
+    test_env.create_gswitch_file("elsewhere/profile", "ci-profile");
+
+    let mut cmd = test_env.command();
+    cmd.args(["prompt", "--file"])
+        .arg(test_env.temp_dir.path().join("elsewhere/profile"));
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("ci-profile"));
+}
+
+#[test]
+fn test_local_sets_repo_email() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "work"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Switched to profile 'work' locally"));
+
+    assert_eq!(test_env.local_git_config("user.email"), Some("work@example.com".to_string()));
+    assert_eq!(test_env.local_git_config("user.name"), Some("Work User".to_string()));
+}
+
+#[test]
+fn test_switch_local_if_repo_inside_repo() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work", "--local-if-repo"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Switched to profile 'work' locally"));
+
+    assert_eq!(test_env.local_git_config("user.email"), Some("work@example.com".to_string()));
+    assert_eq!(test_env.git_config_at(test_env.temp_dir.path(), "--global", "user.email"), None);
+
+    // A local apply doesn't change the globally current profile
+    let mut cmd = test_env.command();
+    cmd.arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("(current)").not());
+}
+
+#[test]
+fn test_switch_local_if_repo_outside_repo() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work", "--local-if-repo"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Switched to profile 'work' globally"));
+
+    assert_eq!(
+        test_env.git_config_at(test_env.temp_dir.path(), "--global", "user.email"),
+        Some("work@example.com".to_string())
+    );
+}
+
+#[test]
+fn test_auto_uses_git_config_profile() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    test_env.git(test_env.temp_dir.path(), &["config", "--local", "gswitch.profile", "work"]);
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--porcelain"]);
+    cmd.assert()
+        .success()
+        .stdout("switched work\n");
+
+    assert_eq!(test_env.local_git_config("user.email"), Some("work@example.com".to_string()));
+}
+
+#[test]
+fn test_list_uses_configured_order() {
+    let test_env = TestEnv::new();
+
+    for (name, email) in [("alpha", "a@example.com"), ("bravo", "b@example.com"), ("charlie", "c@example.com")] {
+        let mut cmd = test_env.command();
+        cmd.args(["add", name, "--user-name", "Test User", "--email", email]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = test_env.command();
+    cmd.args(["reorder", "charlie", "1"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Profile 'charlie' moved to position 1"));
+
+    let output = test_env.command().arg("list").output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let position = |name: &str| stdout.find(&format!("  {} - ", name)).unwrap();
+    assert!(position("charlie") < position("alpha"));
+    assert!(position("alpha") < position("bravo"));
+}
+
+#[test]
+fn test_remove_cleans_referencing_dotfiles() {
+    let test_env = TestEnv::new();
+    test_env.create_gswitch_file("projects/old/.gswitch", "old\n");
+    test_env.create_gswitch_file("projects/keep/.gswitch", "keep\n");
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "old", "--user-name", "Old User", "--email", "old@example.com"]);
+    cmd.assert().success();
+
+    // Without --yes the dangling file is only reported
+    let mut cmd = test_env.command();
+    cmd.args(["remove", "old", "--clean-dotfiles", "projects"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Still references 'old'"));
+    assert!(test_env.temp_dir.path().join("projects/old/.gswitch").exists());
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "old", "--user-name", "Old User", "--email", "old@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["remove", "old", "--clean-dotfiles", "projects", "--yes"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Deleted"));
+    assert!(!test_env.temp_dir.path().join("projects/old/.gswitch").exists());
+    assert!(test_env.temp_dir.path().join("projects/keep/.gswitch").exists());
+}
+
+#[test]
+fn test_profiles_dedupe_rewrites_only_the_duplicate_lines() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    test_env.create_gswitch_file(".gswitch", "host:laptop work\nglobal home\nonly-dotfile\nwork\n");
+    test_env.create_gswitch_file("api/.gswitch", "host:build-01 ci\nwork\n");
+
+    for name in ["acme", "work"] {
+        let mut cmd = test_env.command();
+        cmd.args(["add", name, "--user-name", "Work User", "--email", "work@example.com"]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = test_env.command();
+    cmd.args(["profiles", "dedupe", "--dry-run"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Would keep 'acme', removing: work"))
+        .stdout(predicate::str::contains("api/.gswitch to use 'acme'"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["profile", "dedupe"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Kept 'acme', removed: work"));
+    let read = |path: &str| std::fs::read_to_string(test_env.temp_dir.path().join(path)).unwrap();
+    assert_eq!(read(".gswitch"), "host:laptop acme\nglobal home\nonly-dotfile\nacme\n");
+    assert_eq!(read("api/.gswitch"), "host:build-01 ci\nacme\n");
+}
+
+#[test]
+fn test_remove_clean_dotfiles_keeps_other_lines() {
+    let test_env = TestEnv::new();
+    let shared = "host:laptop legacy\nhost:build-01 ci\nglobal old\nkeep\n";
+    test_env.create_gswitch_file("projects/shared/.gswitch", shared);
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "old", "--user-name", "Old User", "--email", "old@example.com", "--alias", "legacy"]);
+    cmd.assert().success();
+
+    // Lines for other hosts, `global` lines and aliases all still name the profile
+    let mut cmd = test_env.command();
+    cmd.args(["remove", "old", "--clean-dotfiles", "projects", "--yes"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Removed the lines naming 'old'"));
+    assert_eq!(
+        std::fs::read_to_string(test_env.temp_dir.path().join("projects/shared/.gswitch")).unwrap(),
+        "host:build-01 ci\nkeep\n"
+    );
+}
+
+#[test]
+fn test_auto_export_env_includes_custom_vars() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    test_env.create_gswitch_file(".gswitch", "work\n");
+
+    let mut cmd = test_env.command();
+    cmd.args([
+        "add", "work",
+        "--user-name", "Work User",
+        "--email", "work@example.com",
+        "--env", "HTTPS_PROXY=http://proxy.corp:8080",
+        "--env", "CORP_TEAM=platform eng",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--export-env"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("export GIT_AUTHOR_EMAIL=work@example.com"))
+        .stdout(predicate::str::contains("export HTTPS_PROXY=http://proxy.corp:8080"))
+        .stdout(predicate::str::contains("export CORP_TEAM='platform eng'"));
+}
+
+#[test]
+fn test_completions_install_writes_to_shell_location() {
+    let test_env = TestEnv::new();
+    let path = test_env.temp_dir.path().join(".config/fish/completions/gsw.fish");
+
+    let mut cmd = test_env.command();
+    cmd.args(["completions", "fish", "--install"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Installed fish completions"));
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.contains("complete -c gsw"));
+
+    // Reinstalling over our own file is fine
+    let mut cmd = test_env.command();
+    cmd.args(["completions", "fish", "--install"]);
+    cmd.assert().success().stdout(predicate::str::contains("Installed"));
+}
+
+#[test]
+fn test_completions_install_refuses_foreign_file() {
+    let test_env = TestEnv::new();
+    test_env.create_gswitch_file(".config/fish/completions/gsw.fish", "# hand written\n");
+
+    let mut cmd = test_env.command();
+    cmd.args(["completions", "fish", "--install"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Use --force"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["completions", "fish", "--install", "--force"]);
+    cmd.assert().success().stdout(predicate::str::contains("Installed"));
+}
+
+#[test]
+fn test_list_resolve_marks_global_and_repo_profiles() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    test_env.create_gswitch_file(".gswitch", "work\n");
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "personal", "--user-name", "Home User", "--email", "home@example.com"]);
+    cmd.assert().success();
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "personal"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["list", "--resolve"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("personal - Home User <home@example.com> (current) (global)"))
+        .stdout(predicate::str::contains("work - Work User <work@example.com> (repo)"));
+}
+
+#[test]
+fn test_validate_all_clean_config() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let output = test_env.command().args(["validate-all", "--json"]).output().unwrap();
+    assert!(output.status.success());
+    let problems: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(problems, serde_json::json!([]));
+}
+
+#[test]
+fn test_validate_all_broken_config() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "broken", "--user-name", "Broken User", "--email", "not-an-email"]);
+    cmd.assert().success();
+
+    let output = test_env.command().args(["validate-all", "--json"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let problems: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(problems[0]["profile"], "broken");
+    assert_eq!(problems[0]["field"], "email");
+
+    let mut cmd = test_env.command();
+    cmd.arg("validate-all");
+    cmd.assert()
+        .code(1)
+        .stdout(predicate::str::contains("1 problem(s) found"));
+}
+
+#[test]
+fn test_add_with_signing_key_file() {
+    let test_env = TestEnv::new();
+    test_env.create_gswitch_file("secrets/key-id", "  ABCDEF1234567890 \n");
+
+    let mut cmd = test_env.command();
+    cmd.args([
+        "add", "signed",
+        "--user-name", "Signed User",
+        "--email", "signed@example.com",
+        "--signing-key-file", "secrets/key-id",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Signing key: ABCDEF1234567890"));
+}
+
+#[test]
+fn test_add_with_empty_signing_key_file() {
+    let test_env = TestEnv::new();
+    test_env.create_gswitch_file("secrets/key-id", "\n");
+
+    let mut cmd = test_env.command();
+    cmd.args([
+        "add", "signed",
+        "--user-name", "Signed User",
+        "--email", "signed@example.com",
+        "--signing-key-file", "secrets/key-id",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("is empty"));
+
+    let mut cmd = test_env.command();
+    cmd.args([
+        "add", "signed",
+        "--user-name", "Signed User",
+        "--email", "signed@example.com",
+        "--signing-key-file", "secrets/missing",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to read signing key file"));
+}
+
+#[test]
+fn test_switch_dash_returns_to_previous_profile() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "a", "--user-name", "User A", "--email", "a@example.com"]);
+    cmd.assert().success();
+    let mut cmd = test_env.command();
+    cmd.args(["add", "b", "--user-name", "User B", "--email", "b@example.com"]);
+    cmd.assert().success();
+
+    // Nothing to go back to yet
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "-"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("No previous profile"));
+
+    test_env.command().args(["switch", "a"]).assert().success();
+    test_env.command().args(["switch", "b"]).assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "-"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Switched to profile 'a' globally"));
+    assert_eq!(
+        test_env.git_config_at(test_env.temp_dir.path(), "--global", "user.email"),
+        Some("a@example.com".to_string())
+    );
+}
+
+#[cfg(feature = "policy")]
+#[test]
+fn test_policy_sync_installs_managed_profiles_and_works_offline() {
+    let test_env = TestEnv::new();
+    let url = common::serve_once(
+        "[profiles.corp]\nname = \"Corp User\"\nemail = \"me@corp.example\"\n",
+    );
+
+    let mut cmd = test_env.command();
+    cmd.args(["policy", "sync", "--url", &url]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Synced 1 managed profile(s)"));
+
+    let mut cmd = test_env.command();
+    cmd.arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("corp - Corp User <me@corp.example> (managed)"));
+
+    // Managed profiles can be switched to but not edited or removed
+    let mut cmd = test_env.command();
+    cmd.args(["remove", "corp"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("managed by policy"));
+    let mut cmd = test_env.command();
+    cmd.args(["add", "corp", "--user-name", "Me", "--email", "me@home.example"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("managed by policy"));
+    test_env.command().args(["switch", "corp"]).assert().success();
+
+    // The server only answers once, so the next sync falls back to the cache
+    let mut cmd = test_env.command();
+    cmd.args(["policy", "sync"]);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("Could not reach policy server"))
+        .stdout(predicate::str::contains("1 managed profile(s) from the cached bundle"));
+}
+
+#[test]
+fn test_prompt_global_fallback_shows_current_profile() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "personal", "--user-name", "Me", "--email", "me@example.com"]);
+    cmd.assert().success();
+    test_env.command().args(["switch", "personal"]).assert().success();
+
+    // Without the flag nothing applies outside a .gswitch directory
+    let mut cmd = test_env.command();
+    cmd.arg("prompt");
+    cmd.assert().failure().stdout(predicate::str::is_empty());
+
+    let mut cmd = test_env.command();
+    cmd.args(["prompt", "--global-fallback"]);
+    cmd.assert().success().stdout(" personal");
+}
+
+#[test]
+fn test_prompt_global_fallback_without_current_profile() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["prompt", "--global-fallback"]);
+    cmd.assert().failure().stdout(predicate::str::is_empty());
+}
+
+#[test]
+fn test_verbose_traces_git_commands() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "work", "--verbose"]);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("git config --local user.email work@example.com"));
+
+    // Quiet by default
+    let mut cmd = test_env.command();
+    cmd.args(["local", "work"]);
+    cmd.assert().success().stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn test_use_applies_locally_inside_repo() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "oss", "--user-name", "OSS User", "--email", "oss@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["use", "oss"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Switched to profile 'oss' locally"));
+    assert_eq!(test_env.local_git_config("user.email"), Some("oss@example.com".to_string()));
+}
+
+#[test]
+fn test_use_respects_scope_default_inside_repo() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args([
+        "add", "personal", "--user-name", "Me", "--email", "me@example.com",
+        "--scope-default", "global",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["use", "personal"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Switched to profile 'personal' globally"));
+    assert_eq!(
+        test_env.git_config_at(test_env.temp_dir.path(), "--global", "user.email"),
+        Some("me@example.com".to_string())
+    );
+    // The repo's own identity is left alone
+    assert_eq!(test_env.local_git_config("user.email"), Some("test@example.com".to_string()));
+}
+
+#[test]
+fn test_switch_then_runs_command_under_temporary_identity() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work", "--then", "sh", "-c", "git config user.email; exit 3"]);
+    cmd.assert()
+        .code(3)
+        .stdout(predicate::str::contains("work@example.com"));
+
+    // Nothing persisted: the repo identity and global config are untouched
+    assert_eq!(test_env.local_git_config("user.email"), Some("test@example.com".to_string()));
+    assert_eq!(test_env.git_config_at(test_env.temp_dir.path(), "--global", "user.email"), None);
+}
+
+#[test]
+fn test_reconcile_reports_then_fixes_drifted_repos() {
+    let test_env = TestEnv::new();
+    let root = test_env.temp_dir.path().join("code");
+    test_env.init_git_repo_at(root.join("drifted"));
+    test_env.init_git_repo_at(root.join("synced"));
+    test_env.create_gswitch_file("code/drifted/.gswitch", "work");
+    test_env.create_gswitch_file("code/synced/.gswitch", "tester");
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+    let mut cmd = test_env.command();
+    cmd.args(["add", "tester", "--user-name", "Test User", "--email", "test@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["reconcile", "code"]);
+    cmd.assert()
+        .code(1)
+        .stdout(predicate::str::contains("drifted (work)"))
+        .stdout(predicate::str::contains("in sync (tester)"))
+        .stdout(predicate::str::contains("2 repo(s) checked, 1 drifted"));
+    // Report-only leaves the repo alone
+    assert_eq!(
+        test_env.git_config_at(root.join("drifted"), "--local", "user.email"),
+        Some("test@example.com".to_string())
+    );
+
+    let mut cmd = test_env.command();
+    cmd.args(["reconcile", "code", "--apply"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("fixed (work)"))
+        .stdout(predicate::str::contains("2 repo(s) checked, 1 fixed"));
+    assert_eq!(
+        test_env.git_config_at(root.join("drifted"), "--local", "user.email"),
+        Some("work@example.com".to_string())
+    );
+}
+
+#[test]
+fn test_reconcile_follows_auto_resolution() {
+    let test_env = TestEnv::new();
+    let root = test_env.temp_dir.path().join("code");
+    test_env.git(test_env.temp_dir.path(), &["config", "--global", "user.name", "Home User"]);
+    test_env.git(test_env.temp_dir.path(), &["config", "--global", "user.email", "home@example.com"]);
+    for repo in ["global", "ruled"] {
+        let repo = root.join(repo);
+        test_env.init_git_repo_at(&repo);
+        test_env.git(&repo, &["config", "--unset", "user.name"]);
+        test_env.git(&repo, &["config", "--unset", "user.email"]);
+    }
+    test_env.create_gswitch_file("code/global/.gswitch", "home");
+    test_env.git(root.join("ruled"), &["remote", "add", "origin", "git@github.com:acme/app.git"]);
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+    std::fs::write(&config_path, "[profiles]\n\n[[url_rules]]\npattern = \"github.com[:/]acme/\"\nprofile = \"acme\"\n").unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "home", "--user-name", "Home User", "--email", "home@example.com"]);
+    cmd.assert().success();
+    let mut cmd = test_env.command();
+    cmd.args(["add", "acme", "--user-name", "Acme Dev", "--email", "dev@acme.example"]);
+    cmd.assert().success();
+
+    // The global identity already is `home`, so `auto` writes no override and neither does reconcile
+    let mut cmd = test_env.command();
+    cmd.args(["reconcile", "code", "--apply"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("in sync (home)"))
+        .stdout(predicate::str::contains("fixed (acme)"))
+        .stdout(predicate::str::contains("2 repo(s) checked, 1 fixed"));
+    assert_eq!(test_env.git_config_at(root.join("global"), "--local", "user.email"), None);
+    assert_eq!(
+        test_env.git_config_at(root.join("ruled"), "--local", "user.email"),
+        Some("dev@acme.example".to_string())
+    );
+}
+
+#[test]
+fn test_prompt_kv_format() {
+    let test_env = TestEnv::new();
+    test_env.create_gswitch_file(".gswitch", "test-profile");
+
+    let mut cmd = test_env.command();
+    cmd.args(["prompt", "--kv"]);
+    cmd.assert().success().stdout("profile=test-profile\n");
+
+    std::fs::remove_file(test_env.temp_dir.path().join(".gswitch")).unwrap();
+    let mut cmd = test_env.command();
+    cmd.args(["prompt", "--kv"]);
+    cmd.assert().failure().stdout(predicate::str::is_empty());
+}
+
+#[test]
+fn test_import_update_refreshes_existing_profile() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Test User", "--email", "old@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["import", "work"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Profile 'work' already exists"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["import", "work", "--update"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Email: old@example.com -> test@example.com"))
+        .stdout(predicate::str::contains("Name:").not());
+
+    let mut cmd = test_env.command();
+    cmd.arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("work - Test User <test@example.com>"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["import", "work", "--update"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("already matches"));
+}
+
+#[test]
+fn test_import_ssh_config_creates_skeleton_profiles() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    let ssh_config = test_env.temp_dir.path().join("ssh_config");
+    std::fs::write(
+        &ssh_config,
+        "Include config.d/*\nHost github-work\n  HostName github.com\n  IdentityFile ~/.ssh/id_work\nHost *\n  IdentityFile ~/.ssh/id_default\n",
+    )
+    .unwrap();
+    let ssh_config = ssh_config.to_string_lossy().to_string();
+
+    let mut cmd = test_env.command();
+    cmd.args(["import-ssh-config", "--ssh-config", &ssh_config]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("github-work: ssh -i '~/.ssh/id_work' -o IdentitiesOnly=yes"))
+        .stdout(predicate::str::contains("Run with --yes"))
+        .stdout(predicate::str::contains("id_default").not());
+
+    let mut cmd = test_env.command();
+    cmd.arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("No profiles configured"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["import-ssh-config", "--ssh-config", &ssh_config, "--yes"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Created 1 skeleton profile(s)"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["validate-all"]);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("github-work"));
+
+    // A skeleton can't be applied until it has a name and email
+    for args in [["switch", "github-work"], ["use", "github-work"], ["local", "github-work"]] {
+        let mut cmd = test_env.command();
+        cmd.args(args);
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("Profile has no name or email yet"));
+    }
+    assert_eq!(test_env.local_git_config("user.name"), Some("Test User".to_string()));
+
+    let mut cmd = test_env.command();
+    cmd.args(["import-ssh-config", "--ssh-config", &ssh_config]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("github-work: profile already exists, skipping"));
+
+    let mut cmd = test_env.command();
+    cmd.args([
+        "add", "github-work", "--user-name", "Work User", "--email", "work@example.com",
+        "--ssh-command", "ssh -i '~/.ssh/id_work' -o IdentitiesOnly=yes",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "github-work"]);
+    cmd.assert().success();
+    assert_eq!(
+        test_env.local_git_config("core.sshCommand").as_deref(),
+        Some("ssh -i '~/.ssh/id_work' -o IdentitiesOnly=yes")
+    );
+}
+
+#[test]
+fn test_show_config_prints_commands_without_running_them() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args([
+        "add", "work", "--user-name", "Work User", "--email", "work@example.com",
+        "--pull-rebase", "true",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["show-config", "work"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("git config --local user.name 'Work User'\n"))
+        .stdout(predicate::str::contains("git config --local user.email work@example.com\n"))
+        .stdout(predicate::str::contains("git config --local pull.rebase true\n"))
+        .stdout(predicate::str::contains("git config --local --unset-all fetch.prune\n"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["show-config", "work", "--scope", "global"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("git config --global user.email work@example.com"));
+
+    assert_eq!(test_env.local_git_config("user.email"), Some("test@example.com".to_string()));
+}
+
+#[test]
+fn test_no_save_leaves_config_untouched() {
+    let test_env = TestEnv::new();
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com", "--no-save"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Profile 'work' added successfully"));
+    assert!(!config_path.exists());
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+    let saved = std::fs::read_to_string(&config_path).unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.args(["--no-save", "switch", "work"]);
+    cmd.assert().success();
+    assert_eq!(std::fs::read_to_string(&config_path).unwrap(), saved);
+    // Git-side effects still happen
+    assert_eq!(
+        test_env.git_config_at(test_env.temp_dir.path(), "--global", "user.email"),
+        Some("work@example.com".to_string())
+    );
+}
+
+#[test]
+fn test_local_detect_matches_and_adopts_profile() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "tester", "--user-name", "Test User", "--email", "test@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "--detect"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Local identity matches profile 'tester'"));
+    assert_eq!(test_env.local_git_config("gswitch.profile"), None);
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "--detect", "--adopt"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Pinned this repository to 'tester'"));
+    assert_eq!(test_env.local_git_config("gswitch.profile"), Some("tester".to_string()));
+}
+
+#[test]
+fn test_local_detect_without_matching_profile() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "--detect"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("No profile matches the local identity Test User <test@example.com>"))
+        .stdout(predicate::str::contains("gsw import"));
+}
+
+#[test]
+fn test_local_all_worktrees_applies_to_each_worktree() {
+    let test_env = TestEnv::new();
+    let repo = test_env.temp_dir.path().join("repo");
+    let worktree = test_env.temp_dir.path().join("repo-feature");
+    test_env.init_git_repo_at(&repo);
+    assert!(test_env.git(&repo, &["commit", "--allow-empty", "-m", "init"]).status.success());
+    assert!(test_env
+        .git(&repo, &["worktree", "add", "-b", "feature", worktree.to_str().unwrap()])
+        .status
+        .success());
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.current_dir(&repo);
+    cmd.args(["local", "--all-worktrees", "work"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("repo-feature to profile 'work'"));
+
+    for dir in [&repo, &worktree] {
+        assert_eq!(
+            test_env.git_config_at(dir, "--worktree", "user.email"),
+            Some("work@example.com".to_string())
+        );
+    }
+}
+
+#[test]
+fn test_local_all_worktrees_without_extra_worktrees() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "--all-worktrees", "work"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Switched to profile 'work' locally"));
+    assert_eq!(test_env.local_git_config("user.email"), Some("work@example.com".to_string()));
+}
+
+#[test]
+fn test_config_edit_restores_config_after_invalid_edit() {
+    let test_env = TestEnv::new();
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+    let original = std::fs::read_to_string(&config_path).unwrap();
+
+    let editor = test_env.temp_dir.path().join("bad-editor.sh");
+    std::fs::write(&editor, "#!/bin/sh\necho 'profiles = [unclosed' > \"$1\"\n").unwrap();
+    let mut cmd = test_env.command();
+    cmd.args(["config-edit"])
+        .env_remove("VISUAL")
+        .env("EDITOR", format!("sh {}", editor.display()))
+        .write_stdin("n\n");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Config is invalid"))
+        .stdout(predicate::str::contains("Restored the previous config"));
+
+    assert_eq!(std::fs::read_to_string(&config_path).unwrap(), original);
+}
+
+#[test]
+fn test_config_edit_no_save_edits_a_copy() {
+    let test_env = TestEnv::new();
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+
+    let editor = test_env.temp_dir.path().join("editor.sh");
+    std::fs::write(&editor, "#!/bin/sh\nprintf '[profiles.work]\\nname = \"W\"\\nemail = \"w@example.com\"\\n' >> \"$1\"\n").unwrap();
+    let mut cmd = test_env.command();
+    cmd.args(["--no-save", "config-edit"])
+        .env_remove("VISUAL")
+        .env("EDITOR", format!("sh {}", editor.display()));
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Config is valid, but not saved"));
+    assert!(!config_path.exists());
+}
+
+#[test]
+fn test_current_marks_signed_identity() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    test_env.git(test_env.temp_dir.path(), &["config", "--local", "user.signingkey", "ABC123"]);
+
+    // A key alone isn't enough: commits must actually be signed
+    let mut cmd = test_env.command();
+    cmd.arg("current");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with("Current git configuration:"));
+
+    test_env.git(test_env.temp_dir.path(), &["config", "--local", "commit.gpgsign", "true"]);
+    let mut cmd = test_env.command();
+    cmd.arg("current");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with("[signed] Current git configuration:"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["current", "--signed-marker", "🔑", "--color", "always"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with("\x1b[32m🔑\x1b[0m Current git configuration:"));
+}
+
+#[test]
+fn test_add_generate_ssh_key() {
+    let test_env = TestEnv::new();
+    // Fake ssh-keygen: write both halves of the pair at the -f path
+    let path = test_env.stub_program(
+        "ssh-keygen",
+        "while [ $# -gt 0 ]; do [ \"$1\" = -f ] && f=$2; shift; done\n\
+         echo private > \"$f\"; echo 'ssh-ed25519 AAAA work' > \"$f.pub\"\n",
+    );
+
+    let mut cmd = test_env.command();
+    cmd.env("PATH", path).env("HOME", test_env.temp_dir.path());
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com", "--generate-key", "ssh"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Generated SSH signing key"));
+
+    let public_key = test_env.temp_dir.path().join(".ssh/gswitch_work.pub");
+    assert!(public_key.is_file());
+    let mut cmd = test_env.command();
+    cmd.arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(format!("Signing key: {}", public_key.display())))
+        .stdout(predicate::str::contains("Signing format: ssh"));
+}
+
+#[test]
+fn test_add_generate_key_validates_first() {
+    let test_env = TestEnv::new();
+    let path = test_env.stub_program("ssh-keygen", "touch \"$HOME/keygen-ran\"\n");
+
+    let mut cmd = test_env.command();
+    cmd.env("PATH", &path).env("HOME", test_env.temp_dir.path());
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com", "--generate-key", "ssh"]);
+    cmd.args(["--init-default-branch", "bad..name"]);
+    cmd.assert().failure();
+
+    let mut cmd = test_env.command();
+    cmd.env("PATH", &path).env("HOME", test_env.temp_dir.path());
+    cmd.args(["add", "../work", "--user-name", "Work User", "--email", "work@example.com", "--generate-key", "ssh"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("the name can't contain"));
+
+    assert!(!test_env.temp_dir.path().join("keygen-ran").exists());
+}
+
+#[test]
+fn test_add_generate_gpg_key() {
+    let test_env = TestEnv::new();
+    let path = test_env.stub_program(
+        "gpg",
+        "case \"$*\" in\n\
+         *--quick-generate-key*) exit 0 ;;\n\
+         *--list-secret-keys*) echo 'sec:u:255:22:CCCC3333:1710000000:::u:::scESC:::+:::ed25519:::0:'; \
+         echo 'fpr:::::::::0123456789ABCDEF0123456789ABCDEF01234567:' ;;\n\
+         esac\n",
+    );
+
+    let mut cmd = test_env.command();
+    cmd.env("PATH", path);
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com", "--generate-key", "gpg"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Generated GPG signing key 0123456789ABCDEF0123456789ABCDEF01234567"));
+
+    let mut cmd = test_env.command();
+    cmd.arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Signing key: 0123456789ABCDEF0123456789ABCDEF01234567"));
+}
+
+#[test]
+fn test_strict_fails_on_duplicate_identity_warning() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Me", "--email", "me@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work-copy", "--user-name", "Me", "--email", "me@example.com"]);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("Warning: Profile 'work-copy' has the same identity as 'work'"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["--strict", "add", "work-again", "--user-name", "Me", "--email", "me@example.com"]);
+    cmd.assert()
+        .code(1)
+        .stderr(predicate::str::contains("Warning: Profile 'work-again' has the same identity as 'work'"))
+        .stderr(predicate::str::contains("strict mode"));
+
+    // Unrelated profiles don't warn
+    let mut cmd = test_env.command();
+    cmd.args(["--strict", "add", "home", "--user-name", "Me", "--email", "me@home.example"]);
+    cmd.assert().success().stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn test_save_writes_through_symlinked_config() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let test_env = TestEnv::new();
+    let dotfiles = test_env.temp_dir.path().join("dotfiles");
+    std::fs::create_dir_all(&dotfiles).unwrap();
+    let target = dotfiles.join("gswitch.toml");
+    std::fs::write(&target, "[profiles]\n").unwrap();
+    std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o600)).unwrap();
+    let config_dir = test_env.temp_dir.path().join(".config/gswitch");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::os::unix::fs::symlink(&target, config_dir.join("config.toml")).unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let link = std::fs::symlink_metadata(config_dir.join("config.toml")).unwrap();
+    assert!(link.file_type().is_symlink());
+    assert!(std::fs::read_to_string(&target).unwrap().contains("work@example.com"));
+    assert_eq!(std::fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o600);
+}
+
+#[test]
+fn test_strict_still_reports_command_error() {
+    let test_env = TestEnv::new();
+    let config_dir = test_env.temp_dir.path().join(".config/gswitch");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config.toml"),
+        "[profiles.bad]\nname = 1\n\n[profiles.ok]\nname = \"Ok User\"\nemail = \"ok@example.com\"\n",
+    )
+    .unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.args(["--strict", "--lenient", "rename", "missing", "other"]);
+    cmd.assert()
+        .code(1)
+        .stderr(predicate::str::contains("Warning: Skipping invalid profile bad"))
+        .stderr(predicate::str::contains("Error: Profile 'missing' not found"));
+}
+
+#[test]
+fn test_strict_applies_to_commands_without_config() {
+    let test_env = TestEnv::new();
+    let config_dir = test_env.temp_dir.path().join(".config/gswitch");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config.toml"),
+        "[profiles.work]\nname = \"Work User\"\nemail = \"work@example.com\"\nsigning_key = \"~/.ssh/missing.pub\"\nsigning_format = \"ssh\"\n",
+    )
+    .unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.arg("doctor");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("No problems found"))
+        .stderr(predicate::str::contains("Warning: Profile 'work' can't sign"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["--strict", "doctor"]);
+    cmd.assert()
+        .code(1)
+        .stderr(predicate::str::contains("strict mode"));
+}
+
+#[test]
+fn test_auto_no_boundary_uses_dotfile_above_repo_root() {
+    let test_env = TestEnv::new();
+    let repo = test_env.temp_dir.path().join("work/repo");
+    test_env.init_git_repo_at(&repo);
+    test_env.create_gswitch_file("work/.gswitch", "work");
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.current_dir(&repo).env("HOME", test_env.temp_dir.path());
+    cmd.args(["auto", "--porcelain"]);
+    cmd.assert().code(4).stdout("no-profile\n");
+
+    let mut cmd = test_env.command();
+    cmd.current_dir(&repo).env("HOME", test_env.temp_dir.path());
+    cmd.args(["auto", "--porcelain", "--no-boundary"]);
+    cmd.assert().success().stdout("switched work\n");
+    assert_eq!(
+        test_env.git_config_at(&repo, "--local", "user.email"),
+        Some("work@example.com".to_string())
+    );
+
+    // prompt --walk finds the same file only with --no-boundary
+    let mut cmd = test_env.command();
+    cmd.current_dir(&repo).env("HOME", test_env.temp_dir.path());
+    cmd.args(["prompt", "--walk"]);
+    cmd.assert().code(1).stdout("");
+
+    let mut cmd = test_env.command();
+    cmd.current_dir(&repo).env("HOME", test_env.temp_dir.path());
+    cmd.args(["prompt", "--walk", "--no-boundary"]);
+    cmd.assert().success().stdout(" work");
+}
+
+#[test]
+fn test_hooks_install_and_uninstall_keep_existing_hook() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    let hooks_dir = test_env.temp_dir.path().join(".git/hooks");
+    std::fs::create_dir_all(&hooks_dir).unwrap();
+    let existing = "#!/bin/sh\necho existing\n";
+    std::fs::write(hooks_dir.join("post-checkout"), existing).unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.args(["hooks", "install"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Installed post-checkout hook"))
+        .stdout(predicate::str::contains("Installed post-merge hook"));
+
+    let hook = std::fs::read_to_string(hooks_dir.join("post-checkout")).unwrap();
+    assert!(hook.starts_with(existing));
+    assert!(hook.contains("# >>> gswitch >>>"));
+    assert!(hook.contains("gsw auto"));
+    assert!(hooks_dir.join("post-merge").exists());
+
+    let mut cmd = test_env.command();
+    cmd.args(["hooks", "install"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("post-checkout hook already installed"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["hooks", "uninstall"]);
+    cmd.assert().success();
+    assert_eq!(std::fs::read_to_string(hooks_dir.join("post-checkout")).unwrap(), existing);
+    assert!(!hooks_dir.join("post-merge").exists());
+}
+
+#[test]
+fn test_diff_live_reports_differing_email() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Test User", "--email", "test@example.com"]);
+    cmd.assert().success();
+
+    // A key the profile doesn't set is left in place by switching, so it isn't drift
+    test_env.git(test_env.temp_dir.path(), &["config", "user.signingkey", "ABC123"]);
+    let mut cmd = test_env.command();
+    cmd.args(["diff-live", "work", "--scope", "local"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("matches the live git config"));
+
+    test_env.git(test_env.temp_dir.path(), &["config", "user.email", "other@example.com"]);
+
+    let mut cmd = test_env.command();
+    cmd.args(["diff-live", "work", "--scope", "local"]);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "user.email: test@example.com (stored) != other@example.com (live)",
+        ))
+        .stdout(predicate::str::contains("user.name").not());
+}
+
+#[test]
+fn test_gswitch_env_selects_separate_config() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.env("GSWITCH_ENV", "work");
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+    assert!(test_env.temp_dir.path().join(".config/gswitch/config.work.toml").exists());
+
+    let mut cmd = test_env.command();
+    cmd.arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("No profiles configured"));
+
+    let mut cmd = test_env.command();
+    cmd.env("GSWITCH_ENV", "work");
+    cmd.arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("work - Work User <work@example.com>"));
+}
+
+#[test]
+fn test_list_recent_orders_by_last_use() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    for name in ["alpha", "beta", "gamma"] {
+        let mut cmd = test_env.command();
+        let email = format!("{}@example.com", name);
+        cmd.args(["add", name, "--user-name", "Test User", "--email", &email]);
+        cmd.assert().success();
+    }
+    for name in ["gamma", "alpha"] {
+        let mut cmd = test_env.command();
+        cmd.args(["local", name]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = test_env.command();
+    cmd.args(["list", "--recent"]);
+    let output = cmd.output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let order: Vec<&str> = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("  ")?.split(" - ").next())
+        .collect();
+    assert_eq!(order, ["alpha", "gamma", "beta"]);
+}
+
+#[test]
+fn test_local_only_email_keeps_name() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "work", "--only", "email"]);
+    cmd.assert().success();
+    assert_eq!(test_env.local_git_config("user.email").as_deref(), Some("work@example.com"));
+    assert_eq!(test_env.local_git_config("user.name").as_deref(), Some("Test User"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "work", "--only", "name,bogus"]);
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_switch_only_keeps_current_profile() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+    let mut cmd = test_env.command();
+    cmd.args(["add", "personal", "--user-name", "Home User", "--email", "home@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "personal"]);
+    cmd.assert().success();
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work", "--only", "email"]);
+    cmd.assert().success();
+
+    let config = std::fs::read_to_string(test_env.temp_dir.path().join(".config/gswitch/config.toml")).unwrap();
+    assert!(config.contains("current_profile = \"personal\""));
+}
+
+#[test]
+fn test_prompt_distinguish_exit_codes() {
+    let test_env = TestEnv::new();
+
+    // Outside a repo: 2 with --distinguish, the usual 1 without
+    let mut cmd = test_env.command();
+    cmd.args(["prompt", "--distinguish"]);
+    cmd.assert().code(2).stdout("");
+
+    let mut cmd = test_env.command();
+    cmd.arg("prompt");
+    cmd.assert().code(1).stdout("");
+
+    // Inside a repo without a profile: 1 either way
+    test_env.init_git_repo();
+    let mut cmd = test_env.command();
+    cmd.args(["prompt", "--distinguish"]);
+    cmd.assert().code(1).stdout("");
+
+    test_env.create_gswitch_file(".gswitch", "work");
+    let mut cmd = test_env.command();
+    cmd.args(["prompt", "--distinguish"]);
+    cmd.assert().success().stdout(" work");
+}
+
+#[test]
+fn test_auto_skips_local_override_when_global_matches() {
+    let test_env = TestEnv::new();
+    let output = test_env.git(test_env.temp_dir.path(), &["init"]);
+    assert!(output.status.success());
+    test_env.create_gswitch_file(".gswitch", "work");
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--porcelain"]);
+    cmd.assert().success().stdout(predicate::str::contains("unchanged"));
+    assert_ne!(test_env.local_git_config("user.email").as_deref(), Some("dev@acme.example"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--porcelain", "--always-local"]);
+    cmd.assert().success().stdout(predicate::str::contains("switched"));
+    assert_eq!(test_env.local_git_config("user.email").as_deref(), Some("work@example.com"));
+}
+
+#[test]
+fn test_set_email_changes_only_email() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args([
+        "add", "agency", "--user-name", "Dev User", "--email", "dev+client-a@agency.example",
+        "--signing-key", "ABCDEF1234567890",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["set-email", "agency", "dev+client-b@agency.example"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "email: dev+client-a@agency.example -> dev+client-b@agency.example",
+        ));
+
+    let mut cmd = test_env.command();
+    cmd.arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("agency - Dev User <dev+client-b@agency.example>"))
+        .stdout(predicate::str::contains("Signing key: ABCDEF1234567890"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["set-email", "agency", "not-an-email"]);
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_current_scope_all_shows_each_layer() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    test_env.git(test_env.temp_dir.path(), &["config", "--global", "user.email", "global@example.com"]);
+
+    let mut cmd = test_env.command();
+    cmd.args(["current", "--scope", "all"]);
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<Vec<&str>> = stdout.lines().map(|line| line.split_whitespace().collect()).collect();
+
+    assert_eq!(lines[0], ["KEY", "SYSTEM", "GLOBAL", "LOCAL", "EFFECTIVE"]);
+    assert_eq!(
+        lines[2],
+        ["user.email", "-", "global@example.com", "test@example.com", "test@example.com"]
+    );
+    assert_eq!(lines[3], ["user.signingkey", "-", "-", "-", "-"]);
+}
+
+#[test]
+fn test_clean_config_fixes_dangling_references() {
+    let test_env = TestEnv::new();
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+    std::fs::write(
+        &config_path,
+        "current_profile = \"deleted\"\n\n\
+         [profiles.work]\n\
+         name = \"Work User \"\n\
+         email = \"work@example.com\"\n\
+         signing_key = \"\"\n\n\
+         [profiles.work.env]\n",
+    )
+    .unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.args(["clean-config", "--dry-run"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Removed current_profile 'deleted'"))
+        .stdout(predicate::str::contains("Dry run"));
+    assert!(std::fs::read_to_string(&config_path).unwrap().contains("deleted"));
+
+    let mut cmd = test_env.command();
+    cmd.arg("clean-config");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Trimmed whitespace around name of 'work'"))
+        .stdout(predicate::str::contains("Removed empty signing_key from 'work'"));
+
+    let content = std::fs::read_to_string(&config_path).unwrap();
+    assert!(!content.contains("deleted"));
+    assert!(!content.contains("signing_key"));
+    assert!(!content.contains("env"));
+    assert!(content.contains("name = \"Work User\""));
+
+    let mut cmd = test_env.command();
+    cmd.arg("clean-config");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Config is already clean"));
+}
+
+#[test]
+fn test_pin_key_sets_local_signing_key() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["pin-key", "/nonexistent/team_key.pub"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("not found"));
+    assert_eq!(test_env.local_git_config("user.signingkey"), None);
+
+    let key = test_env.temp_dir.path().join("team_key.pub");
+    std::fs::write(&key, "ssh-ed25519 AAAA team\n").unwrap();
+    let key = key.to_string_lossy().to_string();
+
+    let mut cmd = test_env.command();
+    cmd.args(["pin-key", &key, "--origin", "issued by release team"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Pinned signing key"));
+    assert_eq!(test_env.local_git_config("user.signingkey"), Some(key));
+    assert_eq!(
+        test_env.local_git_config("gswitch.keyOrigin").as_deref(),
+        Some("issued by release team")
+    );
+}
+
+#[test]
+fn test_auto_url_rule_selects_profile_by_org() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    test_env.git(test_env.temp_dir.path(), &["remote", "add", "origin", "git@github.com:acme/app.git"]);
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+    std::fs::write(
+        &config_path,
+        "[profiles]\n\n[[url_rules]]\npattern = \"github.com[:/](?P<org>[^/]+)/\"\nprofile = \"personal\"\n",
+    )
+    .unwrap();
+
+    // Saving keeps the rules alongside the profiles
+    let mut cmd = test_env.command();
+    cmd.args(["add", "acme", "--user-name", "Acme Dev", "--email", "dev@acme.example"]);
+    cmd.assert().success();
+    assert!(std::fs::read_to_string(&config_path).unwrap().contains("url_rules"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--porcelain"]);
+    cmd.assert().success().stdout("switched acme\n");
+    assert_eq!(test_env.local_git_config("user.email").as_deref(), Some("dev@acme.example"));
+}
+
+#[test]
+fn test_status_json_with_local_override() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    test_env.git(test_env.temp_dir.path(), &["config", "--global", "user.name", "Global User"]);
+    test_env.git(test_env.temp_dir.path(), &["config", "--global", "user.email", "global@example.com"]);
+    test_env.create_gswitch_file(".gswitch", "work");
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["status", "--json"]);
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(status["global"]["email"], "global@example.com");
+    assert_eq!(status["local"]["email"], "test@example.com");
+    assert_eq!(status["local"]["signing_key"], serde_json::Value::Null);
+    assert_eq!(status["dotfile_profile"], "work");
+    assert_eq!(status["resolved"], "work");
+    assert_eq!(status["in_sync"], false);
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "work"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["status", "--json"]);
+    let output = cmd.output().unwrap();
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(status["local"]["email"], "work@example.com");
+    assert_eq!(status["in_sync"], true);
+}
+
+#[test]
+fn test_on_switch_hook_runs_after_switch() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    let sentinel = test_env.temp_dir.path().join("hook-ran");
+    let hook = format!("echo work > '{}'", sentinel.display());
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com", "--on-switch", &hook]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "work", "--no-hooks"]);
+    cmd.assert().success();
+    assert!(!sentinel.exists());
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "work"]);
+    cmd.assert().success();
+    assert_eq!(std::fs::read_to_string(&sentinel).unwrap(), "work\n");
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "broken", "--user-name", "Work User", "--email", "broken@example.com", "--on-switch", "exit 3"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "broken"]);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("on_switch hook for 'broken' failed"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "broken", "--strict"]);
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_list_filter() {
+    let test_env = TestEnv::new();
+    for (name, email) in [("work", "dev@acme.example"), ("personal", "me@home.example")] {
+        let mut cmd = test_env.command();
+        cmd.args(["add", name, "--user-name", "Test User", "--email", email]);
+        cmd.assert().success();
+    }
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["list", "--filter", "ACME"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("work - Test User <dev@acme.example> (current)"))
+        .stdout(predicate::str::contains("personal").not());
+
+    let mut cmd = test_env.command();
+    cmd.args(["list", "--filter", "person"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("personal - Test User"))
+        .stdout(predicate::str::contains("work").not());
+
+    let mut cmd = test_env.command();
+    cmd.args(["list", "--filter", "nothing-like-this"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("No matching profiles"));
+}
+
+#[test]
+fn test_lenient_load_skips_malformed_profile() {
+    let test_env = TestEnv::new();
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+    let content = "[profiles.work]\nname = \"Work User\"\nemail = \"work@example.com\"\n\n\
+                   [profiles.broken]\nname = \"Broken\"\n";
+    std::fs::write(&config_path, content).unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.arg("list");
+    cmd.assert().failure();
+
+    let mut cmd = test_env.command();
+    cmd.args(["list", "--lenient"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("work - Work User <work@example.com>"))
+        .stderr(predicate::str::contains("Skipping invalid profile broken"));
+
+    // Changes aren't saved while a profile is skipped, so it isn't lost
+    let mut cmd = test_env.command();
+    cmd.args(["add", "personal", "--user-name", "Me", "--email", "me@example.com", "--lenient"]);
+    cmd.assert().success();
+    assert_eq!(std::fs::read_to_string(&config_path).unwrap(), content);
+}
+
+#[test]
+fn test_switch_confirm_identity_waits_for_enter() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com", "--signing-key", "ABC123"]);
+    cmd.assert().success();
+
+    // Closing stdin without pressing Enter cancels the switch
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work", "--confirm-identity"]);
+    cmd.write_stdin("");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Work User <work@example.com>"))
+        .stdout(predicate::str::contains("Signing key: ABC123"))
+        .stdout(predicate::str::contains("Switch cancelled"));
+    assert!(!test_env.global_git_config().exists());
+
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work", "--confirm-identity"]);
+    cmd.write_stdin("\n");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Switched to profile 'work' globally"));
+    let email = test_env.git_config_at(test_env.temp_dir.path(), "--global", "user.email");
+    assert_eq!(email.as_deref(), Some("work@example.com"));
+}
+
+#[test]
+fn test_dotfile_at_configured_subpath() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+    std::fs::write(
+        &config_path,
+        "dotfile_subpath = \".config/gswitch/profile\"\n\n\
+         [profiles.work]\nname = \"Work User\"\nemail = \"work@example.com\"\n",
+    )
+    .unwrap();
+
+    let package = test_env.temp_dir.path().join("packages/app");
+    std::fs::create_dir_all(&package).unwrap();
+    let mut cmd = test_env.command();
+    cmd.current_dir(&package);
+    cmd.args(["init", "work", "--at", ".config/gswitch/profile"]);
+    cmd.assert().success();
+    assert_eq!(
+        std::fs::read_to_string(package.join(".config/gswitch/profile")).unwrap(),
+        "work\n"
+    );
+
+    let src = package.join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    let mut cmd = test_env.command();
+    cmd.current_dir(&src);
+    cmd.args(["auto", "--porcelain"]);
+    cmd.assert().success().stdout(predicate::str::contains("work"));
+    assert_eq!(test_env.local_git_config("user.email").as_deref(), Some("work@example.com"));
+}
+
+#[test]
+fn test_refresh_reapplies_current_profile() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["set-email", "work", "new@example.com"]);
+    cmd.assert().success();
+    let global_email = || test_env.git_config_at(test_env.temp_dir.path(), "--global", "user.email");
+    assert_eq!(global_email().as_deref(), Some("work@example.com"));
+
+    let mut cmd = test_env.command();
+    cmd.arg("refresh");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Re-applied profile 'work' globally"));
+    assert_eq!(global_email().as_deref(), Some("new@example.com"));
+}
+
+#[test]
+fn test_import_from_other_repo_dir() {
+    let test_env = TestEnv::new();
+    let sibling = test_env.temp_dir.path().join("sibling");
+    test_env.init_git_repo_at(&sibling);
+    test_env.git(&sibling, &["config", "user.name", "Sibling User"]);
+    test_env.git(&sibling, &["config", "user.email", "sibling@example.com"]);
+
+    let mut cmd = test_env.command();
+    cmd.args(["import", "sibling", "--dir"]).arg(&sibling);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("sibling - Sibling User <sibling@example.com>"));
+
+    let not_repo = test_env.temp_dir.path().join("plain");
+    std::fs::create_dir_all(&not_repo).unwrap();
+    let mut cmd = test_env.command();
+    cmd.args(["import", "plain", "--dir"]).arg(&not_repo);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("is not a git repository"));
+}
+
+#[test]
+fn test_switch_by_profile_alias() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com", "--alias", "acme", "--alias", "job"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "job"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Switched to profile 'work' globally"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "other", "--user-name", "Other", "--email", "other@example.com", "--alias", "acme"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("alias 'acme' is already an alias of 'work'"));
+}
+
+#[test]
+fn test_export_as_script() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work O'Brien", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["export", "work", "--as-script"]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let script = String::from_utf8(output).unwrap();
+    assert!(script.starts_with("#!/bin/sh\n"));
+    assert!(script.contains("git config --global user.name 'Work O'\\''Brien'\n"));
+    assert!(script.contains("git config --global user.email work@example.com\n"));
+
+    // The script reproduces the profile without gswitch
+    let output = std::process::Command::new("sh")
+        .args(["-c", &script])
+        .env("GIT_CONFIG_GLOBAL", test_env.global_git_config())
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let name = test_env.git_config_at(test_env.temp_dir.path(), "--global", "user.name");
+    assert_eq!(name.as_deref(), Some("Work O'Brien"));
+}
+
+#[test]
+fn test_activate_without_home_or_config_dir() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.env_remove("HOME").env_remove("XDG_CONFIG_HOME");
+    cmd.args(["activate", "bash"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("_gsw_auto_switch"));
+}
+
+#[test]
+fn test_corrupt_config_only_fails_commands_that_need_it() {
+    let test_env = TestEnv::new();
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+    std::fs::write(&config_path, "[profiles\nname = ").unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.args(["activate", "bash"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["completions", "bash"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.arg("list");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to parse config file"))
+        .stderr(predicate::str::contains("config.toml"));
+}
+
+#[test]
+fn test_prompt_with_timeout_reads_file_in_time() {
+    let test_env = TestEnv::new();
+    test_env.create_gswitch_file(".gswitch", "test-profile\n");
+
+    let mut cmd = test_env.command();
+    cmd.args(["prompt", "--timeout", "2000"]);
+    cmd.assert().success().stdout(" test-profile");
+
+    std::fs::remove_file(test_env.temp_dir.path().join(".gswitch")).unwrap();
+    let mut cmd = test_env.command();
+    cmd.args(["prompt", "--timeout", "2000"]);
+    cmd.assert().code(1).stdout(predicate::str::is_empty());
+}
+
+#[test]
+fn test_set_signing_migrates_gpg_to_ssh() {
+    let test_env = TestEnv::new();
+    let key_path = test_env.temp_dir.path().join("id_ed25519.pub");
+    std::fs::write(&key_path, "ssh-ed25519 AAAAC3Nza work@example.com\n").unwrap();
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com", "--signing-key", "ABC123DEF"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["set-signing", "work", "--format", "ssh", "--key"]).arg(&key_path);
+    cmd.args(["--allowed-signers-file", "~/.ssh/allowed_signers"]);
+    cmd.assert().success();
+    let config = std::fs::read_to_string(&config_path).unwrap();
+    assert!(config.contains(&format!("signing_key = \"{}\"", key_path.display())));
+    assert!(config.contains("signing_format = \"ssh\""));
+    assert!(config.contains("allowed_signers_file = \"~/.ssh/allowed_signers\""));
+
+    // An ssh format with a GPG key id is rejected and leaves the profile alone
+    let mut cmd = test_env.command();
+    cmd.args(["set-signing", "work", "--format", "ssh", "--key", "0123ABCD"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("ssh signing needs a public key file"));
+    assert_eq!(std::fs::read_to_string(&config_path).unwrap(), config);
+}
+
+#[test]
+fn test_list_usage_json() {
+    let test_env = TestEnv::new();
+    for name in ["work", "personal"] {
+        let mut cmd = test_env.command();
+        cmd.args(["add", name, "--user-name", "User", "--email"]).arg(format!("{}@example.com", name));
+        cmd.assert().success();
+    }
+    for _ in 0..2 {
+        let mut cmd = test_env.command();
+        cmd.args(["switch", "work"]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = test_env.command();
+    cmd.args(["list", "--usage-json"]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let usage: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    let entry = |name: &str| usage.as_array().unwrap().iter().find(|entry| entry["name"] == name).unwrap().clone();
+
+    let work = entry("work");
+    assert_eq!(work["email"], "work@example.com");
+    assert_eq!(work["use_count"], 2);
+    assert!(work["last_used"].is_u64());
+
+    let personal = entry("personal");
+    assert_eq!(personal["use_count"], 0);
+    assert!(personal["last_used"].is_null());
+    assert!(personal["signing_key"].is_null());
+}
+
+#[test]
+fn test_auto_applies_local_and_global_dotfile_entries() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    for (name, email) in [("work", "work@example.com"), ("personal", "me@example.com")] {
+        let mut cmd = test_env.command();
+        cmd.args(["add", name, "--user-name", "User", "--email", email]);
+        cmd.assert().success();
+    }
+    test_env.create_gswitch_file(".gswitch", "local work\nglobal personal\n");
+
+    let mut cmd = test_env.command();
+    cmd.arg("auto");
+    cmd.assert().success();
+
+    assert_eq!(test_env.local_git_config("user.email").as_deref(), Some("work@example.com"));
+    let global_email = test_env.git_config_at(test_env.temp_dir.path(), "--global", "user.email");
+    assert_eq!(global_email.as_deref(), Some("me@example.com"));
+
+    let config = std::fs::read_to_string(test_env.temp_dir.path().join(".config/gswitch/config.toml")).unwrap();
+    assert!(config.contains("current_profile = \"personal\""));
+}
+
+#[test]
+fn test_rename_profile_follows_current() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["rename", "work", "acme"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Profile 'work' renamed to 'acme'"));
+
+    let config = std::fs::read_to_string(test_env.temp_dir.path().join(".config/gswitch/config.toml")).unwrap();
+    assert!(config.contains("current_profile = \"acme\""));
+    assert!(config.contains("[profiles.acme]"));
+    assert!(!config.contains("[profiles.work]"));
+}
+
+#[test]
+fn test_list_porcelain() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com", "--signing-key", "ABC123"]);
+    cmd.assert().success();
+    let mut cmd = test_env.command();
+    cmd.args(["add", "personal", "--user-name", "Me", "--email", "me@example.com"]);
+    cmd.assert().success();
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["list", "--porcelain"]);
+    cmd.assert()
+        .success()
+        .stdout("personal\tMe\tme@example.com\t\t0\nwork\tWork User\twork@example.com\tABC123\t1\n");
+}
+
+#[test]
+fn test_doctor_fix_clears_dangling_reference() {
+    let test_env = TestEnv::new();
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+    std::fs::write(
+        &config_path,
+        "current_profile = \"ghost\"\n\n[profiles.work]\nname = \" Work User \"\nemail = \"work@example.com\"\n",
+    )
+    .unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.arg("doctor");
+    cmd.assert()
+        .code(1)
+        .stdout(predicate::str::contains("ghost: current_profile references a profile that doesn't exist"))
+        .stdout(predicate::str::contains("gsw doctor --fix"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["doctor", "--fix"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Fixed: Removed current_profile 'ghost'"))
+        .stdout(predicate::str::contains("Fixed: Trimmed whitespace around name of 'work'"))
+        .stdout(predicate::str::contains("No problems found"));
+
+    let mut cmd = test_env.command();
+    cmd.arg("doctor");
+    cmd.assert().success().stdout("No problems found\n");
+}
+
+#[test]
+fn test_doctor_reports_unparseable_config() {
+    let test_env = TestEnv::new();
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+    std::fs::write(&config_path, "[profiles.work\nname = \"Work User\"\n").unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.args(["doctor", "--fix"]);
+    cmd.assert()
+        .code(1)
+        .stdout(predicate::str::contains("config.toml: Failed to parse config file"))
+        .stdout(predicate::str::contains("1 problem(s) found"));
+    assert_eq!(std::fs::read_to_string(&config_path).unwrap(), "[profiles.work\nname = \"Work User\"\n");
+
+    std::fs::write(
+        &config_path,
+        "[profiles.bad]\nname = 1\n\n[profiles.work]\nname = \"Work User\"\nemail = \"work@example.com\"\n",
+    )
+    .unwrap();
+    let mut cmd = test_env.command();
+    cmd.arg("doctor");
+    cmd.assert()
+        .code(1)
+        .stdout(predicate::str::contains("invalid profile bad: invalid type: integer `1`"));
+}
+
+#[test]
+fn test_switch_writes_notify_file() {
+    let test_env = TestEnv::new();
+    let notify_path = test_env.temp_dir.path().join("active-profile");
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+    std::fs::write(
+        &config_path,
+        format!(
+            "notify_file = \"{}\"\n\n[profiles.work]\nname = \"Work User\"\nemail = \"work@example.com\"\n",
+            notify_path.display()
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work"]);
+    cmd.assert().success();
+    assert_eq!(std::fs::read_to_string(&notify_path).unwrap(), "work\n");
+}
+
+#[test]
+fn test_global_include_receives_global_switches() {
+    let test_env = TestEnv::new();
+    let managed = test_env.temp_dir.path().join(".config/gswitch/gitconfig");
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.arg("setup-global-include");
+    cmd.assert().success().stdout(predicate::str::contains("Added include.path"));
+    let include = test_env.git_config_at(test_env.temp_dir.path(), "--global", "include.path");
+    assert_eq!(include.as_deref(), Some(managed.to_str().unwrap()));
+
+    // Running it again doesn't add a second include
+    let mut cmd = test_env.command();
+    cmd.arg("setup-global-include");
+    cmd.assert().success().stdout(predicate::str::contains("already includes"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work"]);
+    cmd.assert().success();
+
+    let managed_content = std::fs::read_to_string(&managed).unwrap();
+    assert!(managed_content.contains("email = work@example.com"));
+    let gitconfig = std::fs::read_to_string(test_env.global_git_config()).unwrap();
+    assert!(!gitconfig.contains("work@example.com"));
+
+    // git picks the identity up through the include
+    let output = test_env.git(test_env.temp_dir.path(), &["config", "--get", "user.email"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "work@example.com");
+}
+
+#[test]
+fn test_test_apply_reports_profile_settings() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com", "--signing-key", "ABC123"]);
+    cmd.args(["--pull-rebase", "true", "--ssh-command", "ssh -i ~/.ssh/work"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["test-apply", "work"]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).unwrap();
+    let settings: Vec<&str> = output.lines().skip(1).map(str::trim).collect();
+    assert_eq!(
+        settings,
+        [
+            "user.name = Work User",
+            "user.email = work@example.com",
+            "user.signingkey = ABC123",
+            "pull.rebase = true",
+            "core.sshCommand = ssh -i ~/.ssh/work",
+        ]
+    );
+}
+
+#[test]
+fn test_sign_hosts_follow_remote() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com", "--sign-host", "git.acme.com"]);
+    cmd.assert().success();
+
+    let dir = test_env.temp_dir.path();
+    test_env.git(dir, &["remote", "add", "origin", "git@git.acme.com:acme/app.git"]);
+    let mut cmd = test_env.command();
+    cmd.args(["local", "work"]);
+    cmd.assert().success();
+    assert_eq!(test_env.local_git_config("commit.gpgsign"), Some("true".to_string()));
+
+    // A remote elsewhere turns signing off, in auto as well as local
+    test_env.git(dir, &["remote", "set-url", "origin", "https://github.com/me/app.git"]);
+    test_env.create_gswitch_file(".gswitch", "work\n");
+    let mut cmd = test_env.command();
+    cmd.arg("auto");
+    cmd.assert().success();
+    assert_eq!(test_env.local_git_config("commit.gpgsign"), Some("false".to_string()));
+
+    // Every local write follows the remote, not just local and auto
+    test_env.git(dir, &["remote", "set-url", "origin", "git@git.acme.com:acme/app.git"]);
+    let mut cmd = test_env.command();
+    cmd.args(["use", "work", "--scope", "local"]);
+    cmd.assert().success();
+    assert_eq!(test_env.local_git_config("commit.gpgsign"), Some("true".to_string()));
+    test_env.git(dir, &["config", "--local", "commit.gpgsign", "false"]);
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work", "--local-if-repo"]);
+    cmd.assert().success();
+    assert_eq!(test_env.local_git_config("commit.gpgsign"), Some("true".to_string()));
+
+    // Resetting doesn't leave signing on without the key it needs
+    let mut cmd = test_env.command();
+    cmd.arg("reset");
+    cmd.assert().success();
+    assert_eq!(test_env.local_git_config("commit.gpgsign"), None);
+}
+
+#[test]
+fn test_import_global_scope_skips_local_override() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    let dir = test_env.temp_dir.path();
+    test_env.git(dir, &["config", "--global", "user.name", "Global User"]);
+    test_env.git(dir, &["config", "--global", "user.email", "global@example.com"]);
+
+    let mut cmd = test_env.command();
+    cmd.args(["import", "mine", "--scope", "global"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Email: global@example.com"));
+
+    // The default still reads the repo's local override
+    let mut cmd = test_env.command();
+    cmd.args(["import", "here"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Email: test@example.com"));
+}
+
+#[test]
+fn test_activate_check_reports_hook_marker() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["activate", "bash", "--check"]).env("GSWITCH_HOOK", "1");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Shell integration is active"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["activate", "bash", "--check"])
+        .env_remove("GSWITCH_HOOK")
+        .env("HOME", test_env.temp_dir.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Shell integration is not active: add gsw activate bash"));
+
+    // Set up in the rc file, but this shell predates it
+    std::fs::write(test_env.temp_dir.path().join(".bashrc"), "eval \"$(gsw activate bash)\"\n").unwrap();
+    let mut cmd = test_env.command();
+    cmd.args(["activate", "bash", "--check"])
+        .env_remove("GSWITCH_HOOK")
+        .env("HOME", test_env.temp_dir.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("start a new shell"));
+}
+
+#[test]
+fn test_list_orphans() {
+    let test_env = TestEnv::new();
+
+    for (name, email) in [("work", "work@example.com"), ("old", "old@example.com")] {
+        let mut cmd = test_env.command();
+        cmd.args(["add", name, "--user-name", "User", "--email", email]);
+        cmd.assert().success();
+    }
+    test_env.create_gswitch_file("projects/app/.gswitch", "work\n");
+
+    let mut cmd = test_env.command();
+    cmd.args(["list", "--orphans", "--root", "projects"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("old - User <old@example.com>"))
+        .stdout(predicate::str::contains("work -").not());
+
+    // Without the tree scan, nothing refers to work either
+    let mut cmd = test_env.command();
+    cmd.args(["list", "--orphans"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("work - User <work@example.com>"));
+}
+
+#[test]
+fn test_global_dir_targets_another_repo() {
+    let test_env = TestEnv::new();
+    let repo = test_env.temp_dir.path().join("elsewhere");
+    test_env.init_git_repo_at(&repo);
+    test_env.git(&repo, &["config", "user.email", "work@example.com"]);
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Test User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["current", "--dir"]).arg(&repo);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("work@example.com"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["init", "work", "--dir"]).arg(&repo);
+    cmd.assert().success();
+    assert_eq!(std::fs::read_to_string(repo.join(".gswitch")).unwrap().trim(), "work");
+    assert!(!test_env.temp_dir.path().join(".gswitch").exists());
+
+    // Relative paths given to the command still start from the current directory
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--log", "auto.log", "--dir"]).arg(&repo);
+    cmd.assert().success();
+    assert!(test_env.temp_dir.path().join("auto.log").is_file());
+    assert!(!repo.join("auto.log").exists());
+
+    let mut cmd = test_env.command();
+    cmd.args(["switch", "work", "--dir"]).arg(&repo);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--dir only applies to"));
+}
+
+#[test]
+fn test_auto_dir_uses_target_repo_remote_and_logs_it() {
+    let test_env = TestEnv::new();
+    // The cwd is a repo too, with a remote no rule matches
+    test_env.init_git_repo();
+    test_env.git(test_env.temp_dir.path(), &["remote", "add", "origin", "git@example.com:me/dotfiles.git"]);
+    let repo = test_env.temp_dir.path().join("elsewhere");
+    test_env.init_git_repo_at(&repo);
+    test_env.git(&repo, &["remote", "add", "origin", "git@github.com:acme/app.git"]);
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+    std::fs::write(&config_path, "[profiles]\n\n[[url_rules]]\npattern = \"github.com[:/]acme/\"\nprofile = \"acme\"\n").unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "acme", "--user-name", "Acme Dev", "--email", "dev@acme.example"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--porcelain", "--log", "auto.log", "--dir"]).arg(&repo);
+    cmd.assert().success().stdout("switched acme\n");
+    assert_eq!(test_env.git_config_at(&repo, "--local", "user.email").as_deref(), Some("dev@acme.example"));
+    assert_ne!(test_env.local_git_config("user.email").as_deref(), Some("dev@acme.example"));
+
+    let log = std::fs::read_to_string(test_env.temp_dir.path().join("auto.log")).unwrap();
+    assert!(log.contains(&format!("cwd={} ", repo.display())), "{}", log);
+    assert!(log.contains("profile=acme applied=true"), "{}", log);
+}
+
+#[test]
+fn test_profile_swap_name_email() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "work@example.com", "--email", "Work User"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["profile", "swap-name-email", "work"]).write_stdin("n\n");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Profile 'work' unchanged"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["profile", "swap-name-email", "work"]).write_stdin("y\n");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Profile 'work' now uses Work User <work@example.com>"))
+        .stderr(predicate::str::contains("Warning").not());
+
+    let mut cmd = test_env.command();
+    cmd.arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("work - Work User <work@example.com>"));
+
+    // Swapping back leaves an email that's really a name, which is worth a warning
+    let mut cmd = test_env.command();
+    cmd.args(["profile", "swap-name-email", "work", "--yes"]);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("'Work User' is not a valid email address"));
+}
+
+#[test]
+fn test_auto_uses_default_dotfile_from_env() {
+    let test_env = TestEnv::new();
+    let repo = test_env.temp_dir.path().join("checkout");
+    test_env.init_git_repo_at(&repo);
+    let default_dotfile = test_env.temp_dir.path().join("ci.gswitch");
+    std::fs::write(&default_dotfile, "ci\n").unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "ci", "--user-name", "CI Bot", "--email", "ci@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.arg("auto").current_dir(&repo).env("GSWITCH_DEFAULT_DOTFILE", &default_dotfile);
+    cmd.assert().success();
+    assert_eq!(test_env.git_config_at(&repo, "--local", "user.email"), Some("ci@example.com".to_string()));
+}
+
+#[test]
+fn test_list_check_keys_flags_missing_key() {
+    let test_env = TestEnv::new();
+    let present = test_env.temp_dir.path().join("present.pub");
+    std::fs::write(&present, "ssh-ed25519 AAAAC3Nza work\n").unwrap();
+    let missing = test_env.temp_dir.path().join("missing.pub");
+
+    for (name, key) in [("work", &present), ("old", &missing)] {
+        let mut cmd = test_env.command();
+        cmd.args(["add", name, "--user-name", "User", "--email", "user@example.com", "--signing-format", "ssh", "--signing-key"])
+            .arg(key);
+        cmd.assert().success();
+    }
+
+    let mut cmd = test_env.command();
+    cmd.args(["list", "--check-keys"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Signing key missing").count(1))
+        .stderr(predicate::str::contains("Profile 'old' can't sign"))
+        .stderr(predicate::str::contains("Profile 'work' can't sign").not());
+
+    let mut cmd = test_env.command();
+    cmd.args(["list", "--check-keys", "--strict"]);
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_current_from_commit_author() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    let dir = test_env.temp_dir.path();
+    let commit = test_env.git(dir, &["-c", "user.name=Work User", "-c", "user.email=work@example.com", "commit", "--allow-empty", "-m", "init"]);
+    assert!(commit.status.success());
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["current", "--from-commit", "HEAD", "--identify"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Name: Work User"))
+        .stdout(predicate::str::contains("Email: work@example.com"))
+        .stdout(predicate::str::contains("(profile: work)"));
+
+    // An annotated tag resolves to its commit's author, not the tagger
+    let tag = test_env.git(dir, &["-c", "user.name=Release Bot", "-c", "user.email=bot@example.com", "tag", "-a", "v1", "-m", "release"]);
+    assert!(tag.status.success());
+    let mut cmd = test_env.command();
+    cmd.args(["current", "--from-commit", "v1"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Name: Work User"))
+        .stdout(predicate::str::contains("Email: work@example.com"))
+        .stdout(predicate::str::contains("Release Bot").not())
+        .stdout(predicate::str::contains("tagger").not());
+
+    let mut cmd = test_env.command();
+    cmd.args(["current", "--from-commit", "no-such-ref"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("'no-such-ref' is not a commit here"));
+}
+
+#[test]
+fn test_profiles_sort_rewrites_config_in_order() {
+    let test_env = TestEnv::new();
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+    std::fs::write(
+        &config_path,
+        r#"[profiles.zulu]
+name = "Zulu"
+email = "zulu@example.com"
+aliases = ["z", "last"]
+
+[profiles.alpha]
+name = "Alpha"
+email = "alpha@example.com"
+"#,
+    )
+    .unwrap();
+
+    let mut cmd = test_env.command();
+    cmd.arg("profiles-sort");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Sorted 2 profile(s)"));
+
+    let content = std::fs::read_to_string(&config_path).unwrap();
+    let alpha = content.find("[profiles.alpha]").unwrap();
+    let zulu = content.find("[profiles.zulu]").unwrap();
+    assert!(alpha < zulu, "profiles not sorted:\n{}", content);
+    assert!(content.contains("\"last\",\n    \"z\","), "aliases not sorted:\n{}", content);
+}
+
+#[test]
+fn test_auto_with_dotfile_naming_alias() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com", "--alias", "acme"]);
+    cmd.assert().success();
+    test_env.create_gswitch_file(".gswitch", "acme\n");
+
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--porcelain"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("switched work"));
+    assert_eq!(test_env.local_git_config("user.email"), Some("work@example.com".to_string()));
+
+    let mut cmd = test_env.command();
+    cmd.args(["list", "--resolve"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("(repo)"));
+
+    // Everything that resolves a .gswitch reports the profile's own name
+    let dir = test_env.temp_dir.path();
+    let mut cmd = test_env.command();
+    cmd.arg("reconcile").arg(dir);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("in sync (work)"));
+
+    test_env.create_gswitch_file("other/.gswitch", "work\n");
+    let mut cmd = test_env.command();
+    cmd.arg("generate-includeif").arg("--root").arg(dir).arg("--output").arg(dir.join("includes"));
+    cmd.assert().success();
+    assert!(dir.join("includes/work.gitconfig").is_file());
+    assert!(!dir.join("includes/acme.gitconfig").exists());
+}
+
+#[test]
+fn test_shell_init_detects_shell() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.arg("shell-init").env("SHELL", "/bin/zsh");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("add-zsh-hook chpwd _gsw_auto_switch"));
+
+    let mut cmd = test_env.command();
+    cmd.arg("shell-init").env("SHELL", "/usr/bin/tcsh");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("gsw activate <shell>"));
+}
+
+#[test]
+fn test_proxy_follows_local_switch() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+
+    let mut cmd = test_env.command();
+    cmd.args([
+        "add", "work", "--user-name", "Work User", "--email", "work@example.com",
+        "--http-proxy", "http://proxy.acme.com:3128",
+    ]);
+    cmd.assert().success();
+    let mut cmd = test_env.command();
+    cmd.args(["add", "personal", "--user-name", "Home User", "--email", "home@example.com"]);
+    cmd.assert().success();
+
+    let mut cmd = test_env.command();
+    cmd.args(["local", "work"]);
+    cmd.assert().success();
+    assert_eq!(test_env.local_git_config("http.proxy").as_deref(), Some("http://proxy.acme.com:3128"));
+
+    // Switching away must not leave personal traffic going through the proxy
+    let mut cmd = test_env.command();
+    cmd.args(["local", "personal"]);
+    cmd.assert().success();
+    assert_eq!(test_env.local_git_config("http.proxy"), None);
+}
+
+#[test]
+fn test_version_verbose() {
+    let test_env = TestEnv::new();
+
+    let mut cmd = test_env.command();
+    cmd.args(["version", "--verbose"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(format!("gsw {}", env!("CARGO_PKG_VERSION"))))
+        .stdout(predicate::str::contains("git: git version"))
+        .stdout(predicate::str::contains("config.toml"));
+
+    let mut cmd = test_env.command();
+    cmd.arg("version");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("git:").not());
+}
+
+#[test]
+fn test_auto_home_fallback() {
+    let test_env = TestEnv::new();
+    let home = test_env.temp_dir.path().join("home");
+    std::fs::create_dir_all(&home).unwrap();
+    std::fs::write(home.join(".gswitch"), "personal\n").unwrap();
+    let repo = test_env.temp_dir.path().join("repo");
+    test_env.init_git_repo_at(&repo);
+
+    let config_path = test_env.temp_dir.path().join(".config/gswitch/config.toml");
+    let profiles = "[profiles.personal]\nname = \"Home User\"\nemail = \"home@example.com\"\n";
+    std::fs::write(&config_path, profiles).unwrap();
+
+    // Off by default: ~/.gswitch doesn't apply to a repo outside it
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--porcelain"]).current_dir(&repo).env("HOME", &home);
+    cmd.assert().code(4).stdout(predicate::str::contains("no-profile"));
+    let mut cmd = test_env.command();
+    cmd.args(["prompt", "--walk"]).current_dir(&repo).env("HOME", &home);
+    cmd.assert().code(1).stdout("");
+
+    std::fs::write(&config_path, format!("home_fallback = true\n\n{}", profiles)).unwrap();
+    let mut cmd = test_env.command();
+    cmd.args(["auto", "--porcelain"]).current_dir(&repo).env("HOME", &home);
+    cmd.assert().success().stdout(predicate::str::contains("switched personal"));
+    assert_eq!(test_env.git_config_at(&repo, "--local", "user.email"), Some("home@example.com".to_string()));
+    let mut cmd = test_env.command();
+    cmd.args(["prompt", "--walk"]).current_dir(&repo).env("HOME", &home);
+    cmd.assert().success().stdout(" personal");
+}
+
+#[test]
+fn test_global_switch_keeps_users_own_settings() {
+    let test_env = TestEnv::new();
+    let home = test_env.temp_dir.path();
+    let own = [
+        ("pull.rebase", "merges"),
+        ("init.defaultBranch", "trunk"),
+        ("core.sshCommand", "ssh -F ~/.ssh/own"),
+        ("http.proxy", "http://own-proxy:8080"),
+    ];
+    for (key, value) in own {
+        test_env.git(home, &["config", "--global", key, value]);
+    }
+
+    let mut cmd = test_env.command();
+    cmd.args(["add", "work", "--user-name", "Work User", "--email", "work@example.com", "--fetch-prune"]);
+    cmd.assert().success();
+    let mut cmd = test_env.command();
+    cmd.args(["add", "plain", "--user-name", "Plain User", "--email", "plain@example.com"]);
+    cmd.assert().success();
+
+    for name in ["work", "plain"] {
+        let mut cmd = test_env.command();
+        cmd.args(["switch", name]);
+        cmd.assert().success();
+    }
+
+    for (key, value) in own {
+        assert_eq!(test_env.git_config_at(home, "--global", key).as_deref(), Some(value), "{} was removed", key);
+    }
+    // What gswitch wrote itself is still cleared on switching away
+    assert_eq!(test_env.git_config_at(home, "--global", "fetch.prune"), None);
+}
+
+#[test]
+fn test_auto_reports_unusable_dotfile() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    test_env.create_gswitch_file(".gswitch", "work/extra\n");
+
+    let mut cmd = test_env.command();
+    cmd.arg("auto");
+    cmd.assert()
+        .stderr(predicate::str::contains("names an invalid profile"));
+
+    let mut cmd = test_env.command();
+    cmd.args(["status"]);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("names an invalid profile"));
+}
+
+#[test]
+fn test_prompt_with_directive_dotfile() {
+    let test_env = TestEnv::new();
+
+    test_env.create_gswitch_file(".gswitch", "local work\nglobal personal\n");
+    let mut cmd = test_env.command();
+    cmd.arg("prompt");
+    cmd.assert().success().stdout(" work");
+
+    test_env.create_gswitch_file(".gswitch", "host:no-such-machine work\npersonal\nonly-dotfile\n");
+    let mut cmd = test_env.command();
+    cmd.arg("prompt");
+    cmd.assert().success().stdout(" personal");
+}
+
+#[test]
+fn test_prompt_walk_finds_dotfile_up_to_repo_root() {
+    let test_env = TestEnv::new();
+    test_env.init_git_repo();
+    test_env.create_gswitch_file(".gswitch", "work\n");
+    let src = test_env.temp_dir.path().join("src/app");
+    std::fs::create_dir_all(&src).unwrap();
+
+    // Without --walk only the current directory is checked
+    let mut cmd = test_env.command();
+    cmd.current_dir(&src).arg("prompt");
+    cmd.assert().code(1).stdout("");
+
+    let mut cmd = test_env.command();
+    cmd.current_dir(&src).args(["prompt", "--walk"]);
+    cmd.assert().success().stdout(" work");
+
+    // A nearer dotfile wins, and so does the configured subpath
+    std::fs::write(
+        test_env.temp_dir.path().join(".config/gswitch/config.toml"),
+        "dotfile_subpath = \".config/gswitch/profile\"\n\n[profiles]\n",
+    )
+    .unwrap();
+    test_env.create_gswitch_file("src/.config/gswitch/profile", "oss\n");
+    let mut cmd = test_env.command();
+    cmd.current_dir(&src).args(["prompt", "--walk", "--kv"]);
+    cmd.assert().success().stdout("profile=oss\n");
+}